@@ -1,16 +1,18 @@
 use crate::command::Command;
 use crate::command::CommandResult;
-use crate::commands::install::Install;
+use crate::commands::install;
 use clap::ArgMatches;
 use derive_more::Display;
 use derive_more::Error;
 use log::*;
 use serde::Deserialize;
 use serde::Serialize;
+use smaug_lib::lockfile;
+use smaug_lib::registry;
 use std::env;
 use std::path::Path;
 use std::path::PathBuf;
-use toml_edit::{value, Document};
+use toml_edit::{value, Document, InlineTable};
 use dunce;
 
 pub struct Add;
@@ -21,8 +23,10 @@ pub enum Error {
     FileNotFound { path: PathBuf },
     #[display(fmt = "{} has already been added to this project.", "name")]
     AlreadyAdded { name: String },
-    #[display(fmt = "Could not fetch from registry.")]
-    Registry,
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Could not fetch from registry: {}", "reason")]
+    Registry { reason: String },
     #[display(fmt = "Could not install packages.")]
     Install,
 }
@@ -55,7 +59,7 @@ impl Command for Add {
         };
 
         let path = Path::new(&canonical);
-        let path = dunce::canonicalize(&path).expect("Could not find path");
+        let path = dunce::canonicalize(path).expect("Could not find path");
 
         let config_path = path.join("Smaug.toml");
 
@@ -63,13 +67,25 @@ impl Command for Add {
             return Err(Box::new(Error::FileNotFound { path: config_path }));
         }
 
+        let registries = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config.registries,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+
         let config =
             std::fs::read_to_string(config_path.clone()).expect("Could not read Smaug.toml");
 
         let package_name = matches.value_of("PACKAGE").expect("No package given");
-        let latest_version = match fetch_from_registry(package_name.to_string()) {
+        let registry = matches.value_of("registry");
+        let latest_version = match registry::url(registry, &registries)
+            .and_then(|url| fetch_from_registry(url.as_str(), package_name.to_string()))
+        {
             Ok(version) => version,
-            Err(..) => return Err(Box::new(Error::Registry)),
+            Err(err) => {
+                return Err(Box::new(Error::Registry {
+                    reason: err.to_string(),
+                }))
+            }
         };
 
         trace!("Latest version: {}", latest_version);
@@ -88,12 +104,33 @@ impl Command for Add {
             }
         }
 
-        doc["dependencies"][package_name] = value(latest_version.clone());
+        doc["dependencies"][package_name] = match registry {
+            Some(registry) => {
+                let mut options = InlineTable::default();
+                options.get_or_insert("version", latest_version.as_str());
+                options.get_or_insert("registry", registry);
+                options.fmt();
+                value(options)
+            }
+            None => value(latest_version.clone()),
+        };
 
-        std::fs::write(config_path, doc.to_string_in_original_order())
+        std::fs::write(config_path.clone(), doc.to_string_in_original_order())
             .expect("Couldn't write config file.");
 
-        if Install.run(matches).is_err() {
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+
+        let lockfile_path = path.join("Smaug.lock");
+        let lockfile = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => return Err(Box::new(Error::Install)),
+        };
+
+        let allow_incompatible = matches.is_present("allow_incompatible");
+        if install::install(&path, &config, lockfile, None, allow_incompatible).is_err() {
             return Err(Box::new(Error::Install));
         }
 
@@ -114,8 +151,8 @@ struct PackageResponse {
     version: VersionResponse,
 }
 
-fn fetch_from_registry(name: String) -> std::io::Result<String> {
-    let url = format!("https://api.smaug.dev/packages/{}", name);
+fn fetch_from_registry(registry: &str, name: String) -> std::io::Result<String> {
+    let url = format!("{}/packages/{}", registry, name);
     trace!("Fetching latest version from {}", url);
 
     let response = reqwest::blocking::get(url.as_str());
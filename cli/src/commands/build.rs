@@ -1,5 +1,5 @@
 use crate::command::CommandResult;
-use crate::{command::Command, game_metadata};
+use crate::{command::Command, dev_dependencies, game_metadata};
 use clap::ArgMatches;
 use derive_more::Display;
 use derive_more::Error;
@@ -85,10 +85,12 @@ impl Command for Build {
                 trace!("Cleaning build directory");
                 rm_rf::ensure_removed(&build_dir).expect("couldn't clean build directory");
                 trace!("Cleaning builds directory");
-                rm_rf::ensure_removed(&builds_directory).expect("couldn't clean build directory");
+                rm_rf::ensure_removed(builds_directory).expect("couldn't clean build directory");
 
                 copy_directory(&path, build_dir.clone())
                     .expect("Could not copy to build directory.");
+                dev_dependencies::strip(&build_dir)
+                    .expect("Could not leave dev-dependencies out of the build.");
 
                 let log_dir = build_dir.join("logs");
                 let exception_dir = build_dir.join("exceptions");
@@ -34,7 +34,7 @@ impl Command for Docs {
             .unwrap_or_else(|| current_directory.to_str().unwrap());
         debug!("Directory: {}", directory);
         let path = Path::new(directory);
-        let path = dunce::canonicalize(&path).expect("Could not find path");
+        let path = dunce::canonicalize(path).expect("Could not find path");
 
         let config_path = path.join("Smaug.toml");
 
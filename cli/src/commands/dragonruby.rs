@@ -8,15 +8,11 @@ use clap::ArgMatches;
 use install::Install;
 use list::List;
 use log::*;
-use serde::Serialize;
-use std::fmt::Display;
 use uninstall::Uninstall;
 
 #[derive(Debug)]
 pub struct DragonRuby;
 
-trait Result: Display + Serialize {}
-
 impl Command for DragonRuby {
     fn run(&self, matches: &ArgMatches) -> CommandResult {
         trace!("Dragon Ruby Command");
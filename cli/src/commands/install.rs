@@ -1,13 +1,23 @@
 use crate::command::Command;
 use crate::command::CommandResult;
+use crate::command::Json;
 use clap::ArgMatches;
 use derive_more::Display;
 use derive_more::Error;
 use log::*;
 use question::{Answer, Question};
+use resolver::Require;
 use resolver::Resolver;
 use serde::Serialize;
-use smaug_lib::{dependency::Dependency, resolver};
+use smaug_lib::config::Config;
+use smaug_lib::lockfile::Lockfile;
+use smaug_lib::manifest;
+use smaug_lib::manifest::InstalledFile;
+use smaug_lib::manifest::Manifest;
+use smaug_lib::util::digest;
+use smaug_lib::util::dir;
+use smaug_lib::{dependency::Dependency, lockfile, resolver};
+use std::collections::HashSet;
 use std::env;
 use std::path::Path;
 use std::path::PathBuf;
@@ -31,6 +41,10 @@ enum Error {
     FileNotFound { path: PathBuf },
     #[display(fmt = "Couldn't load Smaug configuration.")]
     Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+    #[display(fmt = "{} is not a valid number of jobs.", "value")]
+    Jobs { value: String },
 }
 
 impl Command for Install {
@@ -51,7 +65,7 @@ impl Command for Install {
             }
         };
         let path = Path::new(&canonical);
-        let path = dunce::canonicalize(&path).expect("Could not find path");
+        let path = dunce::canonicalize(path).expect("Could not find path");
 
         let config_path = path.join("Smaug.toml");
 
@@ -61,20 +75,98 @@ impl Command for Install {
         };
         debug!("Smaug config: {:?}", config);
 
-        let mut registry = resolver::new_from_config(&config);
+        let lockfile_path = path.join("Smaug.lock");
+        let lockfile = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
 
-        match registry.install(path.join("smaug")) {
-            Ok(dependencies) => {
-                debug!("{:?}", registry.requires);
-                if install_files(&registry).is_err() {
-                    return Err(Box::new(Error::InstallFailed));
+        let jobs = match matches.value_of("jobs") {
+            Some(value) => match value.parse::<usize>() {
+                Ok(jobs) if jobs > 0 => Some(jobs),
+                _ => {
+                    return Err(Box::new(Error::Jobs {
+                        value: value.to_string(),
+                    }))
                 }
+            },
+            None => None,
+        };
+
+        smaug_lib::smaug::set_offline(matches.is_present("offline"));
+
+        let allow_incompatible = matches.is_present("allow_incompatible");
+        let registry = install(&path, &config, lockfile, jobs, allow_incompatible)?;
+
+        Ok(Box::new(InstallResult {
+            dependencies: registry.requirements,
+        }))
+    }
+}
 
-                write_index(&registry, &path);
+pub fn install(
+    path: &Path,
+    config: &Config,
+    lockfile: Lockfile,
+    jobs: Option<usize>,
+    allow_incompatible: bool,
+) -> Result<Resolver, Box<dyn Json>> {
+    let lockfile_path = path.join("Smaug.lock");
+    let mut registry = match resolver::new_from_config(config) {
+        Ok(registry) => registry,
+        Err(err) => {
+            error!("{}", err);
+            return Err(Box::new(Error::InstallFailed));
+        }
+    };
+    registry.lockfile = lockfile;
+    registry.jobs = jobs;
+    registry.allow_incompatible = allow_incompatible;
+
+    match registry.install(path.join("smaug")) {
+        Ok(..) => {
+            debug!("{:?}", registry.requires);
+            if let Err(err) = install_files(&registry, path) {
+                error!("{}", err);
+                return Err(Box::new(Error::InstallFailed));
+            }
+
+            let dev_only = registry.dev_only();
+            let (dev_requires, requires): (Vec<&Require>, Vec<&Require>) = registry
+                .requires
+                .iter()
+                .partition(|require| dev_only.contains(&require.package));
+
+            write_index(&requires, &path.join("smaug.rb"));
+            info!("Add `require \"smaug.rb\" to the top of your main.rb");
+
+            let dev_index_path = path.join(DEV_INDEX);
+            if !config.dev_dependencies.is_empty() {
+                write_index(&dev_requires, &dev_index_path);
+                info!(
+                    "Add `require \"{}\" below it to load your dev-dependencies",
+                    DEV_INDEX
+                );
+            } else if dev_index_path.is_file() {
+                std::fs::remove_file(&dev_index_path).expect("Could not remove file");
+            }
 
-                Ok(Box::new(InstallResult { dependencies }))
+            trace!("Writing lockfile to {}", lockfile_path.display());
+            if registry.lockfile.write(&lockfile_path).is_err() {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }));
             }
-            Err(..) => Err(Box::new(Error::InstallFailed)),
+
+            Ok(registry)
+        }
+        Err(err) => {
+            error!("{}", err);
+            Err(Box::new(Error::InstallFailed))
         }
     }
 }
@@ -84,8 +176,11 @@ struct Index {
     requires: Vec<String>,
 }
 
+/// Requires the dev-dependencies. Builds replace it with an empty index.
+pub static DEV_INDEX: &str = "smaug_dev.rb";
+
 static INDEX_TEMPLATE: &str = include_str!("../../templates/smaug.rb.template");
-fn write_index(resolver: &Resolver, path: &Path) {
+pub fn write_index(requires: &[&Require], index_path: &Path) {
     trace!("Writing index");
     let mut tt = TinyTemplate::new();
 
@@ -93,7 +188,10 @@ fn write_index(resolver: &Resolver, path: &Path) {
         .expect("couldn't add template.");
 
     let context = Index {
-        requires: resolver.requires.clone(),
+        requires: requires
+            .iter()
+            .map(|require| require.path.clone())
+            .collect(),
     };
 
     debug!("Context: {:?}", context);
@@ -102,21 +200,28 @@ fn write_index(resolver: &Resolver, path: &Path) {
         .render("smaug.rb", &context)
         .expect("Could not render smaug.rb");
 
-    let index_path = path.join("smaug.rb");
     trace!("Writing index to {}", index_path.display());
     std::fs::write(index_path, rendered).expect("Could not write file");
-
-    info!("Add `require \"smaug.rb\" to the top of your main.rb");
 }
 
-fn install_files(resolver: &Resolver) -> std::io::Result<()> {
+/// Copies the files packages install into the project and records them in the
+/// install manifest. Files from an earlier install that no package places anymore
+/// are removed, unless they were edited since.
+fn install_files(resolver: &Resolver, path: &Path) -> std::io::Result<()> {
     trace!("Installing files");
     debug!("{:?}", resolver.installs);
+    let packages_dir = path.join("smaug");
+    let previous = manifest::load(&packages_dir)?;
+    let mut installed = Manifest::default();
+    let mut produced = HashSet::new();
+
     for install in resolver.installs.iter() {
         let source = install.from.as_path();
         let destination = install.to.as_path();
+        let relative = dir::relative(path, destination)?;
+        produced.insert(relative.clone());
 
-        if can_install_file(source, destination) {
+        if can_install_file(source, destination, previous.get(&relative)) {
             trace!(
                 "Copying file from {} to {}",
                 source.display(),
@@ -124,19 +229,50 @@ fn install_files(resolver: &Resolver) -> std::io::Result<()> {
             );
             std::fs::create_dir_all(destination.parent().unwrap())?;
             std::fs::copy(source, destination)?;
+
+            installed.files.retain(|file| file.path != relative);
+            installed.files.push(InstalledFile {
+                path: relative,
+                digest: digest::file(destination)?,
+            });
+        }
+    }
+
+    let mut kept = vec![];
+
+    for file in previous.files.iter() {
+        let destination = file.path.to_path(path);
+
+        if produced.contains(&file.path) || !destination.is_file() {
+            continue;
+        }
+
+        if digest::file(&destination)? == file.digest {
+            trace!("Removing {}", destination.display());
+            std::fs::remove_file(&destination)?;
+        } else {
+            kept.push(destination);
         }
     }
 
-    Ok(())
+    if !kept.is_empty() {
+        warn!("These files are no longer installed but were modified, so they were left alone:");
+
+        for file in kept.iter() {
+            warn!("  * {}", file.display());
+        }
+    }
+
+    installed.write(&packages_dir)
 }
 
-fn can_install_file(source: &Path, destination: &Path) -> bool {
+fn can_install_file(source: &Path, destination: &Path, installed: Option<&InstalledFile>) -> bool {
     if !destination.exists() {
         return true;
     }
 
-    let source_digest = smaug_lib::util::digest::file(source).unwrap();
-    let destination_digest = smaug_lib::util::digest::file(destination).unwrap();
+    let source_digest = digest::file(source).unwrap();
+    let destination_digest = digest::file(destination).unwrap();
     debug!(
         "Source: {}, Destination: {}",
         source_digest, destination_digest
@@ -144,7 +280,10 @@ fn can_install_file(source: &Path, destination: &Path) -> bool {
 
     let changed = source_digest != destination_digest;
 
-    if !changed {
+    // Smaug put this file there and nobody has touched it since.
+    let untouched = installed.is_some_and(|installed| installed.digest == destination_digest);
+
+    if !changed || untouched {
         return true;
     }
 
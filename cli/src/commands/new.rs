@@ -29,7 +29,7 @@ impl Command for New {
         trace!("New Command");
 
         let latest = smaug_lib::dragonruby::latest();
-        if let Err(..) = latest {
+        if latest.is_err() {
             return Err(Box::new(Error::DragonRubyNotFound {}));
         }
         let latest = latest.unwrap();
@@ -0,0 +1,253 @@
+use crate::command::Command;
+use crate::command::CommandResult;
+use crate::table;
+use clap::ArgMatches;
+use derive_more::Display;
+use derive_more::Error;
+use dunce;
+use log::*;
+use semver::VersionReq;
+use serde::Serialize;
+use smaug_lib::config::DependencyOptions;
+use smaug_lib::lockfile;
+use smaug_lib::registry;
+use smaug_lib::sources::git_source;
+use smaug_lib::sources::registry_source;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Outdated;
+
+#[derive(Debug, Display, Serialize)]
+#[display(fmt = "{}", "format_packages(packages)")]
+pub struct OutdatedResult {
+    packages: Vec<PackageStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageStatus {
+    name: String,
+    installed: Option<String>,
+    wanted: Option<String>,
+    latest: Option<String>,
+    installed_commit: Option<String>,
+    latest_commit: Option<String>,
+    outdated: bool,
+    dev: bool,
+}
+
+#[derive(Debug, Display, Error, Serialize)]
+enum Error {
+    #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
+    FileNotFound { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+    #[display(fmt = "Could not fetch {} from the registry.", "name")]
+    Registry { name: String },
+    #[display(
+        fmt = "{} is not a valid version requirement for {}.",
+        "version",
+        "name"
+    )]
+    Version { name: String, version: String },
+    #[display(fmt = "Could not fetch {} from {}: {}", "name", "repo", "reason")]
+    Git {
+        name: String,
+        repo: String,
+        reason: String,
+    },
+}
+
+impl Command for Outdated {
+    fn run(&self, matches: &ArgMatches) -> CommandResult {
+        trace!("Outdated Command");
+
+        let current_directory = env::current_dir().unwrap();
+        let directory: &str = matches
+            .value_of("path")
+            .unwrap_or_else(|| current_directory.to_str().unwrap());
+        debug!("Directory: {}", directory);
+        let path = match dunce::canonicalize(directory) {
+            Ok(dir) => dir,
+            Err(..) => {
+                return Err(Box::new(Error::FileNotFound {
+                    path: Path::new(directory).to_path_buf(),
+                }))
+            }
+        };
+
+        let config_path = path.join("Smaug.toml");
+
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+        debug!("Smaug config: {:?}", config);
+
+        let lockfile_path = path.join("Smaug.lock");
+        let lockfile = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
+
+        let mut packages = vec![];
+
+        let dependencies = config
+            .dependencies
+            .iter()
+            .map(|(name, options)| (name, options, false))
+            .chain(
+                config
+                    .dev_dependencies
+                    .iter()
+                    .map(|(name, options)| (name, options, true)),
+            );
+
+        for (name, options, dev) in dependencies {
+            let installed = installed_version(&path, name);
+            let installed_commit = lockfile
+                .get(name)
+                .and_then(|package| package.commit.clone());
+
+            let options = config.patch.get(name).unwrap_or(options);
+            let status = match options {
+                DependencyOptions::Registry { version, registry } => {
+                    let versions = match registry::url(registry.as_deref(), &config.registries)
+                        .and_then(|url| registry_source::fetch_versions(url.as_str(), name))
+                    {
+                        Ok(versions) => versions,
+                        Err(..) => {
+                            return Err(Box::new(Error::Registry {
+                                name: name.to_string(),
+                            }))
+                        }
+                    };
+
+                    let requirement = match VersionReq::parse(version.as_str()) {
+                        Ok(requirement) => requirement,
+                        Err(..) => {
+                            return Err(Box::new(Error::Version {
+                                name: name.to_string(),
+                                version: version.to_string(),
+                            }))
+                        }
+                    };
+                    let wanted =
+                        registry_source::highest(&versions, |version| requirement.matches(version))
+                            .map(|version| version.to_string());
+                    let latest = registry_source::highest(&versions, |_| true)
+                        .map(|version| version.to_string());
+
+                    PackageStatus {
+                        name: name.to_string(),
+                        outdated: installed.is_none() || installed != latest,
+                        installed,
+                        wanted,
+                        latest,
+                        installed_commit,
+                        latest_commit: None,
+                        dev,
+                    }
+                }
+                DependencyOptions::Git {
+                    repo,
+                    branch,
+                    rev: None,
+                    tag: None,
+                    ..
+                } => {
+                    let latest_commit = match git_source::remote_head(repo, branch.as_deref()) {
+                        Ok(commit) => Some(commit),
+                        Err(err) => {
+                            return Err(Box::new(Error::Git {
+                                name: name.to_string(),
+                                repo: repo.to_string(),
+                                reason: err.to_string(),
+                            }))
+                        }
+                    };
+
+                    PackageStatus {
+                        name: name.to_string(),
+                        outdated: installed_commit != latest_commit,
+                        installed,
+                        wanted: None,
+                        latest: None,
+                        installed_commit,
+                        latest_commit,
+                        dev,
+                    }
+                }
+                _ => PackageStatus {
+                    name: name.to_string(),
+                    outdated: installed.is_none(),
+                    installed,
+                    wanted: None,
+                    latest: None,
+                    installed_commit,
+                    latest_commit: None,
+                    dev,
+                },
+            };
+
+            packages.push(status);
+        }
+
+        Ok(Box::new(OutdatedResult { packages }))
+    }
+}
+
+fn installed_version(path: &Path, name: &str) -> Option<String> {
+    let config_path = path.join("smaug").join(name).join("Smaug.toml");
+
+    smaug_lib::config::load(&config_path)
+        .ok()
+        .and_then(|config| config.package)
+        .map(|package| package.version)
+}
+
+fn format_packages(packages: &[PackageStatus]) -> String {
+    if packages.is_empty() {
+        return "This project has no dependencies.".to_string();
+    }
+
+    let rows: Vec<Vec<String>> = packages
+        .iter()
+        .map(|package| {
+            let (wanted, latest) = match &package.latest_commit {
+                Some(commit) => (table::short_commit(commit), table::short_commit(commit)),
+                None => (
+                    package.wanted.clone().unwrap_or_else(|| "-".to_string()),
+                    package.latest.clone().unwrap_or_else(|| "-".to_string()),
+                ),
+            };
+
+            let name = if package.dev {
+                format!("{} (dev)", package.name)
+            } else {
+                package.name.clone()
+            };
+
+            vec![
+                name,
+                table::format_version(
+                    package.installed.as_deref(),
+                    package.installed_commit.as_deref(),
+                ),
+                wanted,
+                latest,
+                if package.outdated { "outdated" } else { "" }.to_string(),
+            ]
+        })
+        .collect();
+
+    table::format(&["Package", "Installed", "Wanted", "Latest", ""], &rows)
+}
@@ -38,7 +38,7 @@ impl Command for Init {
         trace!("Init Command");
 
         let latest = smaug_lib::dragonruby::latest();
-        if let Err(..) = latest {
+        if latest.is_err() {
             return Err(Box::new(Error::DragonRubyNotFound {}));
         }
         let latest = latest.unwrap();
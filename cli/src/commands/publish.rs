@@ -1,5 +1,5 @@
 use crate::command::CommandResult;
-use crate::{command::Command, game_metadata};
+use crate::{command::Command, dev_dependencies, game_metadata};
 use clap::ArgMatches;
 use derive_more::Display;
 use derive_more::Error;
@@ -49,7 +49,7 @@ impl Command for Publish {
             .unwrap_or_else(|| current_directory.to_str().unwrap());
         debug!("Directory: {}", directory);
         let path = Path::new(directory);
-        let path = dunce::canonicalize(&path).expect("Could not find path");
+        let path = dunce::canonicalize(path).expect("Could not find path");
 
         let config_path = path.join("Smaug.toml");
 
@@ -76,6 +76,8 @@ impl Command for Publish {
 
                 copy_directory(&path, build_dir.clone())
                     .expect("Could not copy to build directory.");
+                dev_dependencies::strip(&build_dir)
+                    .expect("Could not leave dev-dependencies out of the build.");
 
                 let log_dir = build_dir.join("logs");
                 let exception_dir = build_dir.join("exceptions");
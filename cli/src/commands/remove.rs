@@ -0,0 +1,216 @@
+use crate::command::Command;
+use crate::command::CommandResult;
+use clap::ArgMatches;
+use derive_more::Display;
+use derive_more::Error;
+use dunce;
+use log::*;
+use serde::Serialize;
+use smaug_lib::lockfile;
+use smaug_lib::manifest;
+use smaug_lib::receipt;
+use smaug_lib::resolver::Install;
+use smaug_lib::util::digest;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+use toml_edit::Document;
+
+#[derive(Debug)]
+pub struct Remove;
+
+#[derive(Debug, Display, Serialize)]
+#[display(fmt = "{}", "format_removal(package, kept)")]
+pub struct RemoveResult {
+    package: String,
+    uninstalled: Vec<String>,
+    kept: Vec<PathBuf>,
+}
+
+#[derive(Debug, Display, Error, Serialize)]
+enum Error {
+    #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
+    FileNotFound { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+    #[display(fmt = "{} is not a dependency of this project.", "name")]
+    NotADependency { name: String },
+    #[display(fmt = "Could not uninstall {}.", "name")]
+    Uninstall { name: String },
+}
+
+impl Command for Remove {
+    fn run(&self, matches: &ArgMatches) -> CommandResult {
+        trace!("Remove Command");
+
+        let current_directory = env::current_dir().unwrap();
+        let directory: &str = matches
+            .value_of("path")
+            .unwrap_or_else(|| current_directory.to_str().unwrap());
+        debug!("Directory: {}", directory);
+        let path = match dunce::canonicalize(directory) {
+            Ok(dir) => dir,
+            Err(..) => {
+                return Err(Box::new(Error::FileNotFound {
+                    path: Path::new(directory).to_path_buf(),
+                }))
+            }
+        };
+
+        let config_path = path.join("Smaug.toml");
+
+        if !config_path.is_file() {
+            return Err(Box::new(Error::FileNotFound { path: config_path }));
+        }
+
+        let package_name = matches.value_of("PACKAGE").expect("No package given");
+
+        let lockfile_path = path.join("Smaug.lock");
+        let before = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
+
+        let contents =
+            std::fs::read_to_string(config_path.clone()).expect("Could not read Smaug.toml");
+        let mut doc = contents.parse::<Document>().expect("invalid doc");
+
+        let removed = ["dependencies", "dev-dependencies"]
+            .iter()
+            .find_map(|table| {
+                doc[table]
+                    .as_table_mut()
+                    .and_then(|dependencies| dependencies.remove(package_name))
+            });
+
+        if removed.is_none() {
+            return Err(Box::new(Error::NotADependency {
+                name: package_name.to_string(),
+            }));
+        }
+
+        std::fs::write(config_path.clone(), doc.to_string_in_original_order())
+            .expect("Couldn't write config file.");
+
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+
+        // With a manifest, installing cleans up the removed packages' files itself.
+        let tracked = manifest::path(&path.join("smaug")).is_file();
+        let allow_incompatible = matches.is_present("allow_incompatible");
+        let registry = crate::commands::install::install(
+            &path,
+            &config,
+            before.clone(),
+            None,
+            allow_incompatible,
+        )?;
+
+        // Packages that were only installed because of the removed one go too.
+        let mut uninstalled: Vec<String> = before
+            .packages
+            .iter()
+            .filter(|package| registry.lockfile.get(&package.name).is_none())
+            .map(|package| package.name.clone())
+            .collect();
+
+        if !uninstalled.iter().any(|name| name == package_name)
+            && registry.lockfile.get(package_name).is_none()
+        {
+            uninstalled.push(package_name.to_string());
+        }
+
+        let mut kept = vec![];
+
+        for name in uninstalled.iter() {
+            match uninstall(&path, name, tracked) {
+                Ok(mut modified) => kept.append(&mut modified),
+                Err(..) => {
+                    return Err(Box::new(Error::Uninstall {
+                        name: name.to_string(),
+                    }))
+                }
+            }
+        }
+
+        Ok(Box::new(RemoveResult {
+            package: package_name.to_string(),
+            uninstalled,
+            kept,
+        }))
+    }
+}
+
+/// Removes an installed package along with the files it placed in the project.
+/// Returns the placed files that were left alone because they have been modified.
+///
+/// Files listed in `smaug/installed.toml` have already been handled by the install
+/// that ran before this, so they are only looked up here for projects installed
+/// before Smaug kept a manifest.
+fn uninstall(path: &Path, name: &str, tracked: bool) -> std::io::Result<Vec<PathBuf>> {
+    let package_dir = path.join("smaug").join(name);
+    let config_path = package_dir.join("Smaug.toml");
+    let mut kept = vec![];
+
+    let config = if tracked {
+        None
+    } else {
+        smaug_lib::config::load(&config_path).ok()
+    };
+
+    if let Some(config) = config {
+        let installs = config
+            .package
+            .map(|package| package.installs)
+            .unwrap_or_default();
+
+        let mut files = vec![];
+        for (from, to) in installs.iter() {
+            files.append(&mut Install::expand(name, from, to, &package_dir, path)?);
+        }
+
+        for install in files {
+            let source = install.from;
+            let destination = install.to;
+
+            if !destination.is_file() {
+                continue;
+            }
+
+            if source.is_file() && digest::file(&source)? == digest::file(&destination)? {
+                trace!("Removing {}", destination.display());
+                std::fs::remove_file(&destination)?;
+            } else {
+                kept.push(destination);
+            }
+        }
+    }
+
+    trace!("Removing {}", package_dir.display());
+    rm_rf::ensure_removed(&package_dir).expect("Couldn't remove package directory");
+    receipt::remove(&path.join("smaug"), name)?;
+
+    Ok(kept)
+}
+
+fn format_removal(package: &str, kept: &[PathBuf]) -> String {
+    let mut message = format!("Removed {} from your project.", package);
+
+    if !kept.is_empty() {
+        message.push_str("\nThese files were modified and have not been removed:");
+
+        for file in kept.iter() {
+            message.push_str(format!("\n  * {}", file.display()).as_str());
+        }
+    }
+
+    message
+}
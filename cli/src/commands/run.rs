@@ -56,7 +56,7 @@ impl Command for Run {
             .unwrap_or_else(|| current_directory.to_str().unwrap());
         debug!("Directory: {}", directory);
         let path = Path::new(directory);
-        let path = dunce::canonicalize(&path).expect("Could not find path");
+        let path = dunce::canonicalize(path).expect("Could not find path");
         debug!("path: {:?}", path);
 
         let config_path = path.join("Smaug.toml");
@@ -125,7 +125,7 @@ impl Command for Run {
                         .expect("couldn't create local logs");
                 }
 
-                let mut file = File::create(&local_log_dir.join("pid.lock")).unwrap();
+                let mut file = File::create(local_log_dir.join("pid.lock")).unwrap();
                 file.write_fmt(format_args!("{}", child.id())).unwrap();
 
                 let status = child.wait().unwrap();
@@ -0,0 +1,333 @@
+use crate::command::Command;
+use crate::command::CommandResult;
+use clap::ArgMatches;
+use derive_more::Display;
+use derive_more::Error;
+use dunce;
+use log::*;
+use semver::Version;
+use semver::VersionReq;
+use serde::Serialize;
+use smaug_lib::config::DependencyOptions;
+use smaug_lib::config::Patches;
+use smaug_lib::config::Registries;
+use smaug_lib::dependency::Dependency;
+use smaug_lib::lockfile;
+use smaug_lib::lockfile::Lockfile;
+use std::collections::HashSet;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Tree;
+
+#[derive(Debug, Display, Serialize)]
+#[display(fmt = "{}", "format_tree(self)")]
+pub struct TreeResult {
+    #[serde(skip)]
+    format: Format,
+    name: String,
+    dependencies: Vec<Node>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Node {
+    name: String,
+    version: Option<String>,
+    source: String,
+    requirement: String,
+    duplicate: bool,
+    patched: bool,
+    dev: bool,
+    conflict: Option<String>,
+    dependencies: Vec<Node>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Dot,
+}
+
+#[derive(Debug, Display, Error, Serialize)]
+enum Error {
+    #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
+    FileNotFound { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+}
+
+impl Command for Tree {
+    fn run(&self, matches: &ArgMatches) -> CommandResult {
+        trace!("Tree Command");
+
+        let current_directory = env::current_dir().unwrap();
+        let directory: &str = matches
+            .value_of("path")
+            .unwrap_or_else(|| current_directory.to_str().unwrap());
+        debug!("Directory: {}", directory);
+        let path = match dunce::canonicalize(directory) {
+            Ok(dir) => dir,
+            Err(..) => {
+                return Err(Box::new(Error::FileNotFound {
+                    path: Path::new(directory).to_path_buf(),
+                }))
+            }
+        };
+
+        let config_path = path.join("Smaug.toml");
+
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+        debug!("Smaug config: {:?}", config);
+
+        let lockfile_path = path.join("Smaug.lock");
+        let lockfile = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
+
+        let format = match matches.value_of("format") {
+            Some("json") => Format::Json,
+            Some("dot") => Format::Dot,
+            _ => Format::Text,
+        };
+
+        let name = match (&config.project, &config.package) {
+            (Some(project), _) => project.name.clone(),
+            (None, Some(package)) => package.name.clone(),
+            (None, None) => "project".to_string(),
+        };
+
+        let mut builder = TreeBuilder {
+            installed: path.join("smaug"),
+            lockfile,
+            registries: config.registries.clone(),
+            patches: config.patch.clone(),
+            seen: HashSet::new(),
+        };
+        let declared: Vec<(String, DependencyOptions)> = config
+            .dependencies
+            .iter()
+            .map(|(name, options)| (name.clone(), options.clone()))
+            .collect();
+        let dev_declared: Vec<(String, DependencyOptions)> = config
+            .dev_dependencies
+            .iter()
+            .map(|(name, options)| (name.clone(), options.clone()))
+            .collect();
+        let mut dependencies = builder.nodes(&declared, false);
+        dependencies.append(&mut builder.nodes(&dev_declared, true));
+
+        Ok(Box::new(TreeResult {
+            format,
+            name,
+            dependencies,
+        }))
+    }
+}
+
+struct TreeBuilder {
+    installed: PathBuf,
+    lockfile: Lockfile,
+    registries: Registries,
+    patches: Patches,
+    seen: HashSet<String>,
+}
+
+impl TreeBuilder {
+    fn nodes(&mut self, dependencies: &[(String, DependencyOptions)], dev: bool) -> Vec<Node> {
+        dependencies
+            .iter()
+            .map(|(name, options)| self.node(name, options, dev))
+            .collect()
+    }
+
+    // Packages are only expanded the first time they appear, which also keeps cycles finite.
+    // Everything below a dev-dependency is only needed while developing, too.
+    fn node(&mut self, name: &str, options: &DependencyOptions, dev: bool) -> Node {
+        let patched = self.patches.contains_key(name);
+        let options = &self.patches.get(name).unwrap_or(options).clone();
+        let locked = self.lockfile.get(name).cloned();
+        let duplicate = !self.seen.insert(name.to_string());
+        let requirement = match options {
+            DependencyOptions::Registry { version, .. } => version.clone(),
+            _ => smaug_lib::source::from_dependency_options(options, &self.registries)
+                .map(|source| source.id())
+                .unwrap_or_default(),
+        };
+        let conflict = locked
+            .as_ref()
+            .and_then(|locked| conflict(options, locked, &self.registries));
+
+        let mut dependencies = vec![];
+
+        if !duplicate {
+            // Read from where the package was installed from, like `smaug install` does.
+            let config = locked.as_ref().and_then(|locked| {
+                let source =
+                    smaug_lib::source::from_dependency_options(options, &self.registries).ok()?;
+                let dependency = Dependency {
+                    name: name.to_string(),
+                    version: locked.version.clone(),
+                };
+
+                source
+                    .locked(locked)
+                    .package_config(&dependency, &self.installed)
+                    .ok()
+            });
+
+            if let Some(config) = config {
+                let declared: Vec<(String, DependencyOptions)> = config
+                    .dependencies
+                    .iter()
+                    .map(|(name, options)| (name.clone(), options.clone()))
+                    .collect();
+
+                dependencies = self.nodes(&declared, dev);
+            }
+        }
+
+        Node {
+            name: name.to_string(),
+            version: locked.map(|locked| locked.version),
+            source: options.kind().to_string(),
+            requirement,
+            duplicate,
+            patched,
+            dev,
+            conflict,
+            dependencies,
+        }
+    }
+}
+
+/// Describes how the installed package fails to match what this dependent asked for.
+fn conflict(
+    options: &DependencyOptions,
+    locked: &lockfile::LockedPackage,
+    registries: &Registries,
+) -> Option<String> {
+    if let DependencyOptions::Registry { version, .. } = options {
+        let requirement = VersionReq::parse(version.as_str()).ok()?;
+        let installed = Version::parse(locked.version.as_str()).ok()?;
+
+        if requirement.matches(&installed) {
+            return None;
+        }
+
+        return Some(format!("requires {}", requirement));
+    }
+
+    let id = smaug_lib::source::from_dependency_options(options, registries)
+        .ok()?
+        .id();
+
+    if id == locked.source {
+        None
+    } else {
+        Some(format!("requires {}", id))
+    }
+}
+
+fn format_tree(tree: &TreeResult) -> String {
+    match tree.format {
+        Format::Json => serde_json::to_string(tree).expect("Could not convert to json"),
+        Format::Dot => format_dot(tree),
+        Format::Text => {
+            let mut lines = vec![tree.name.clone()];
+            format_nodes(&tree.dependencies, "", &mut lines);
+            lines.join("\n")
+        }
+    }
+}
+
+fn format_nodes(nodes: &[Node], prefix: &str, lines: &mut Vec<String>) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index == nodes.len() - 1;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        lines.push(format!("{}{}{}", prefix, branch, format_node(node)));
+        format_nodes(&node.dependencies, &format!("{}{}", prefix, indent), lines);
+    }
+}
+
+fn format_node(node: &Node) -> String {
+    let mut line = format!(
+        "{} {} ({})",
+        node.name,
+        node.version.as_deref().unwrap_or("not installed"),
+        node.source
+    );
+
+    if node.duplicate {
+        line.push_str(" (*)");
+    }
+
+    if node.patched {
+        line.push_str(" [patched]");
+    }
+
+    if node.dev {
+        line.push_str(" [dev]");
+    }
+
+    if let Some(conflict) = &node.conflict {
+        line.push_str(format!(" [conflict: {}]", conflict).as_str());
+    }
+
+    line
+}
+
+fn format_dot(tree: &TreeResult) -> String {
+    let mut lines = vec![
+        "digraph dependencies {".to_string(),
+        format!("  \"{}\";", tree.name),
+    ];
+    format_dot_edges(&tree.name, &tree.dependencies, &mut lines);
+    lines.push("}".to_string());
+
+    lines.join("\n")
+}
+
+fn format_dot_edges(parent: &str, nodes: &[Node], lines: &mut Vec<String>) {
+    for node in nodes.iter() {
+        let label = format!(
+            "{}\\n{} ({}{}{})",
+            node.name,
+            node.version.as_deref().unwrap_or("not installed"),
+            node.source,
+            if node.patched { ", patched" } else { "" },
+            if node.dev { ", dev" } else { "" }
+        );
+
+        if !node.duplicate {
+            lines.push(format!("  \"{}\" [label=\"{}\"];", node.name, label));
+        }
+
+        match &node.conflict {
+            Some(conflict) => lines.push(format!(
+                "  \"{}\" -> \"{}\" [color=red, label=\"{}\"];",
+                parent, node.name, conflict
+            )),
+            None => lines.push(format!("  \"{}\" -> \"{}\";", parent, node.name)),
+        }
+
+        format_dot_edges(&node.name, &node.dependencies, lines);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::command::Command;
+use crate::command::CommandResult;
+use crate::table;
+use clap::ArgMatches;
+use derive_more::Display;
+use derive_more::Error;
+use dunce;
+use log::*;
+use serde::Serialize;
+use smaug_lib::lockfile;
+use smaug_lib::receipt;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Update;
+
+#[derive(Debug, Display, Serialize)]
+#[display(fmt = "{}", "format_updates(updates)")]
+pub struct UpdateResult {
+    updates: Vec<PackageUpdate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageUpdate {
+    name: String,
+    from: Option<String>,
+    to: String,
+    from_commit: Option<String>,
+    to_commit: Option<String>,
+}
+
+#[derive(Debug, Display, Error, Serialize)]
+enum Error {
+    #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
+    FileNotFound { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+    #[display(fmt = "{} is not a dependency of this project.", "name")]
+    NotADependency { name: String },
+}
+
+impl Command for Update {
+    fn run(&self, matches: &ArgMatches) -> CommandResult {
+        trace!("Update Command");
+
+        let current_directory = env::current_dir().unwrap();
+        let directory: &str = matches
+            .value_of("path")
+            .unwrap_or_else(|| current_directory.to_str().unwrap());
+        debug!("Directory: {}", directory);
+        let path = match dunce::canonicalize(directory) {
+            Ok(dir) => dir,
+            Err(..) => {
+                return Err(Box::new(Error::FileNotFound {
+                    path: Path::new(directory).to_path_buf(),
+                }))
+            }
+        };
+
+        let config_path = path.join("Smaug.toml");
+
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+        debug!("Smaug config: {:?}", config);
+
+        let lockfile_path = path.join("Smaug.lock");
+        let before = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
+
+        let packages: Vec<&str> = matches.values_of("PACKAGE").unwrap_or_default().collect();
+
+        for name in packages.iter() {
+            if before.get(name).is_none() && !config.dependencies.contains_key(*name) {
+                return Err(Box::new(Error::NotADependency {
+                    name: name.to_string(),
+                }));
+            }
+        }
+
+        let destination = path.join("smaug");
+        let mut unlocked = before.clone();
+        unlocked.packages.retain(|package| {
+            let selected = packages.is_empty() || packages.contains(&package.name.as_str());
+            // The receipt has the options the package was installed with. Without one the
+            // package is reinstalled anyway, so there is nothing worth keeping locked.
+            let updatable = match receipt::load(&destination, &package.name) {
+                Some(receipt) => receipt.options.is_updatable(),
+                None => true,
+            };
+
+            !(selected && updatable)
+        });
+
+        let allow_incompatible = matches.is_present("allow_incompatible");
+        let registry =
+            crate::commands::install::install(&path, &config, unlocked, None, allow_incompatible)?;
+
+        let updates = registry
+            .lockfile
+            .packages
+            .iter()
+            .filter(|package| packages.is_empty() || packages.contains(&package.name.as_str()))
+            .map(|package| {
+                let previous = before.get(&package.name);
+
+                PackageUpdate {
+                    name: package.name.clone(),
+                    from: previous.map(|previous| previous.version.clone()),
+                    to: package.version.clone(),
+                    from_commit: previous.and_then(|previous| previous.commit.clone()),
+                    to_commit: package.commit.clone(),
+                }
+            })
+            .collect();
+
+        Ok(Box::new(UpdateResult { updates }))
+    }
+}
+
+fn format_updates(updates: &[PackageUpdate]) -> String {
+    if updates.is_empty() {
+        return "No dependencies to update.".to_string();
+    }
+
+    let rows: Vec<Vec<String>> = updates
+        .iter()
+        .map(|update| {
+            vec![
+                update.name.clone(),
+                table::format_version(update.from.as_deref(), update.from_commit.as_deref()),
+                table::format_version(Some(update.to.as_str()), update.to_commit.as_deref()),
+            ]
+        })
+        .collect();
+
+    table::format(&["Package", "Before", "After"], &rows)
+}
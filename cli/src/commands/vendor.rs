@@ -0,0 +1,192 @@
+use crate::command::Command;
+use crate::command::CommandResult;
+use clap::ArgMatches;
+use derive_more::Display;
+use derive_more::Error;
+use dunce;
+use log::*;
+use serde::Serialize;
+use smaug_lib::lockfile;
+use smaug_lib::lockfile::Lockfile;
+use smaug_lib::util::dir::copy_directory;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+use toml_edit::{table, value, Document, InlineTable};
+
+static HEADER: &str = "# This file is automatically @generated by Smaug.
+# It lists where the vendored packages originally came from. Run `smaug vendor` to update it.
+
+";
+
+#[derive(Debug)]
+pub struct Vendor;
+
+#[derive(Debug, Display, Serialize)]
+#[display(
+    fmt = "Vendored {} packages into {}.",
+    "packages.len()",
+    "directory.display()"
+)]
+pub struct VendorResult {
+    directory: PathBuf,
+    packages: Vec<String>,
+}
+
+#[derive(Debug, Display, Error, Serialize)]
+enum Error {
+    #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
+    FileNotFound { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug configuration.")]
+    Config { path: PathBuf },
+    #[display(fmt = "Couldn't load Smaug.lock at {}", "path.display()")]
+    Lockfile { path: PathBuf },
+    #[display(fmt = "Could not vendor {}: {}", "name", "reason")]
+    Copy { name: String, reason: String },
+}
+
+impl Command for Vendor {
+    fn run(&self, matches: &ArgMatches) -> CommandResult {
+        trace!("Vendor Command");
+
+        let current_directory = env::current_dir().unwrap();
+        let directory: &str = matches
+            .value_of("path")
+            .unwrap_or_else(|| current_directory.to_str().unwrap());
+        debug!("Directory: {}", directory);
+        let path = match dunce::canonicalize(directory) {
+            Ok(dir) => dir,
+            Err(..) => {
+                return Err(Box::new(Error::FileNotFound {
+                    path: Path::new(directory).to_path_buf(),
+                }))
+            }
+        };
+
+        let config_path = path.join("Smaug.toml");
+
+        let config = match smaug_lib::config::load(&config_path) {
+            Ok(config) => config,
+            Err(..) => return Err(Box::new(Error::Config { path: config_path })),
+        };
+        debug!("Smaug config: {:?}", config);
+
+        let lockfile_path = path.join("Smaug.lock");
+        let lockfile = match lockfile::load(&lockfile_path) {
+            Ok(lockfile) => lockfile,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: lockfile_path,
+                }))
+            }
+        };
+
+        // Relative to the project, like every other path in Smaug.toml.
+        let vendor_dir = PathBuf::from(matches.value_of("DIR").unwrap_or("vendor"));
+        let vendor_path = path.join(&vendor_dir);
+        let manifest_path = vendor_path.join("vendor.toml");
+        let previous = match lockfile::load(&manifest_path) {
+            Ok(previous) => previous,
+            Err(..) => {
+                return Err(Box::new(Error::Lockfile {
+                    path: manifest_path,
+                }))
+            }
+        };
+
+        let allow_incompatible = matches.is_present("allow_incompatible");
+        let registry =
+            crate::commands::install::install(&path, &config, lockfile, None, allow_incompatible)?;
+
+        let mut manifest = Lockfile::default();
+        let mut packages = vec![];
+
+        for package in registry.lockfile.packages.iter() {
+            let package_dir = vendor_dir.join(&package.name);
+
+            trace!("Vendoring {} into {}", package.name, package_dir.display());
+            if let Err(err) = vendor_package(&path, &package.name, &path.join(&package_dir)) {
+                return Err(Box::new(Error::Copy {
+                    name: package.name.clone(),
+                    reason: err.to_string(),
+                }));
+            }
+
+            let vendored_dir = dunce::canonicalize(path.join(&package_dir))
+                .expect("Couldn't find vendored package");
+            let vendored_source = format!("dir+{}", vendored_dir.display());
+
+            // Vendoring again installs from the vendored copy, which says nothing about
+            // where the package came from in the first place.
+            let original = match previous.get(&package.name) {
+                Some(original) if package.source == vendored_source => original.clone(),
+                _ => package.clone(),
+            };
+
+            manifest.packages.push(original);
+            packages.push(package.name.clone());
+        }
+
+        let mut stale = vec![];
+
+        for package in previous.packages.iter() {
+            if registry.lockfile.get(&package.name).is_none() {
+                rm_rf::ensure_removed(vendor_path.join(&package.name))
+                    .expect("Couldn't remove vendored package");
+                stale.push(package.name.clone());
+            }
+        }
+
+        std::fs::create_dir_all(&vendor_path).expect("Couldn't create vendor directory.");
+        let manifest = manifest.relative_to(&vendor_path);
+        let contents = toml::to_string(&manifest).expect("Could not serialize vendor manifest");
+        std::fs::write(&manifest_path, format!("{}{}", HEADER, contents))
+            .expect("Couldn't write vendor manifest.");
+
+        let contents =
+            std::fs::read_to_string(config_path.clone()).expect("Could not read Smaug.toml");
+        let mut doc = contents.parse::<Document>().expect("invalid doc");
+
+        if doc["patch"].is_none() {
+            doc["patch"] = table();
+        }
+
+        for name in packages.iter() {
+            let mut options = InlineTable::default();
+            options.get_or_insert("dir", patch_dir(&vendor_dir, name));
+            options.fmt();
+            doc["patch"][name.as_str()] = value(options);
+        }
+
+        // Patches for packages that were vendored before but aren't anymore would point
+        // at directories that were just removed. Patches added by hand are left alone.
+        for name in stale.iter() {
+            if doc["patch"][name.as_str()]["dir"].as_str() == Some(&patch_dir(&vendor_dir, name)) {
+                doc["patch"]
+                    .as_table_mut()
+                    .and_then(|patches| patches.remove(name));
+            }
+        }
+
+        std::fs::write(config_path, doc.to_string_in_original_order())
+            .expect("Couldn't write config file.");
+
+        Ok(Box::new(VendorResult {
+            directory: vendor_dir,
+            packages,
+        }))
+    }
+}
+
+/// The `dir` of the `[patch]` entry for a vendored package.
+fn patch_dir(vendor_dir: &Path, name: &str) -> String {
+    vendor_dir.join(name).to_string_lossy().replace('\\', "/")
+}
+
+/// Replaces the vendored copy of a package with what is installed in `smaug/`.
+fn vendor_package(path: &Path, name: &str, destination: &Path) -> std::io::Result<()> {
+    let installed = path.join("smaug").join(name);
+
+    rm_rf::ensure_removed(destination).expect("Couldn't remove vendored package");
+    copy_directory(&installed, destination.to_path_buf())
+}
@@ -0,0 +1,61 @@
+use crate::commands::install;
+use log::*;
+use smaug_lib::lockfile;
+use smaug_lib::receipt;
+use smaug_lib::resolver::Install;
+use smaug_lib::util::digest;
+use std::io;
+use std::path::Path;
+
+/// Takes the dev-dependencies out of a copy of the project before it is handed to
+/// dragonruby-publish. The dev index is emptied rather than removed so main.rb
+/// can keep requiring it.
+pub fn strip(build_dir: &Path) -> io::Result<()> {
+    let lockfile = lockfile::load(&build_dir.join("Smaug.lock"))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let packages_dir = build_dir.join("smaug");
+
+    for package in lockfile.packages.iter().filter(|package| package.dev) {
+        let package_dir = packages_dir.join(&package.name);
+        trace!("Leaving {} out of the build", package.name);
+
+        let installs = smaug_lib::config::load(&package_dir.join("Smaug.toml"))
+            .ok()
+            .and_then(|config| config.package)
+            .map(|package| package.installs)
+            .unwrap_or_default();
+
+        let mut files = vec![];
+        for (from, to) in installs.iter() {
+            files.append(&mut Install::expand(
+                &package.name,
+                from,
+                to,
+                &package_dir,
+                build_dir,
+            )?);
+        }
+
+        for install in files {
+            let source = install.from;
+            let destination = install.to;
+
+            if source.is_file()
+                && destination.is_file()
+                && digest::file(&source)? == digest::file(&destination)?
+            {
+                std::fs::remove_file(&destination)?;
+            }
+        }
+
+        rm_rf::ensure_removed(&package_dir).expect("Couldn't remove package directory");
+        receipt::remove(&packages_dir, &package.name)?;
+    }
+
+    let dev_index_path = build_dir.join(install::DEV_INDEX);
+    if dev_index_path.is_file() {
+        install::write_index(&[], &dev_index_path);
+    }
+
+    Ok(())
+}
@@ -2,7 +2,9 @@ extern crate derive_more;
 
 mod command;
 mod commands;
+mod dev_dependencies;
 mod game_metadata;
+mod table;
 
 use crate::command::Command;
 use crate::commands::bind::Bind;
@@ -12,7 +14,8 @@ use clap::clap_app;
 use commands::install::Install;
 use commands::{
     add::Add, build::Build, config::Config, docs::Docs, dragonruby::DragonRuby, init::Init,
-    new::New, publish::Publish,
+    new::New, outdated::Outdated, publish::Publish, remove::Remove, tree::Tree, update::Update,
+    vendor::Vendor,
 };
 use log::*;
 
@@ -32,7 +35,7 @@ fn main() {
             (setting: clap::AppSettings::SubcommandRequiredElseHelp)
             (@subcommand install =>
                 (about: "Installs DragonRuby.")
-                (@arg FILE: +required "The location of the DragonRuby Game Toolkit zip file.")
+                (@arg FILE: +required "The location of the DragonRuby Game Toolkit zip file or tarball.")
             )
             (@subcommand uninstall =>
                 (about: "Uninstalls DragonRuby.")
@@ -93,15 +96,48 @@ fn main() {
             (about: "Adds a dependency to the project.")
             (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
             (@arg PACKAGE: +required "The package to add to your project's dependencies")
+            (@arg registry: --registry +takes_value "The registry to add the package from. Defaults to the Smaug registry.")
+            (@arg allow_incompatible: --("allow-incompatible") "Install packages made for another DragonRuby version or edition, with a warning.")
         )
         (@subcommand install =>
             (about: "Installs dependencies from Smaug.toml.")
             (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+            (@arg offline: --offline "Only install packages that are already in the cache.")
+            (@arg jobs: --jobs -j +takes_value "How many packages to download at once. Defaults to the number of CPUs.")
+            (@arg allow_incompatible: --("allow-incompatible") "Install packages made for another DragonRuby version or edition, with a warning.")
+        )
+        (@subcommand outdated =>
+            (about: "Lists dependencies that have newer versions available.")
+            (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+        )
+        (@subcommand remove =>
+            (about: "Removes a dependency from the project.")
+            (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+            (@arg PACKAGE: +required "The package to remove from your project's dependencies")
+            (@arg allow_incompatible: --("allow-incompatible") "Install packages made for another DragonRuby version or edition, with a warning.")
+        )
+        (@subcommand tree =>
+            (about: "Displays the project's dependency tree.")
+            (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+            (@arg format: --format -f +takes_value possible_value[text json dot] "The output format. Defaults to text.")
+        )
+        (@subcommand vendor =>
+            (about: "Copies every dependency into the project so it installs without the network.")
+            (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+            (@arg DIR: "Where to put the packages, relative to your project. Defaults to vendor.")
+            (@arg allow_incompatible: --("allow-incompatible") "Install packages made for another DragonRuby version or edition, with a warning.")
+        )
+        (@subcommand update =>
+            (about: "Updates dependencies to the newest versions allowed by Smaug.toml.")
+            (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
+            (@arg PACKAGE: ... "The packages to update. Defaults to every dependency.")
+            (@arg allow_incompatible: --("allow-incompatible") "Install packages made for another DragonRuby version or edition, with a warning.")
         )
         (@subcommand add =>
             (about: "Add a dependency to Smaug.toml")
             (@arg path: --path -p +takes_value "The path to your project. Defaults to the current directory.")
             (@arg PACKAGE: +required "The location of a package to add")
+            (@arg registry: --registry +takes_value "The registry to add the package from. Defaults to the Smaug registry.")
         )
         (@subcommand config =>
             (about: "Displays your current project's Smaug configuration")
@@ -120,6 +156,11 @@ fn main() {
         Some("dragonruby") => Some(Box::new(DragonRuby)),
         Some("init") => Some(Box::new(Init)),
         Some("install") => Some(Box::new(Install)),
+        Some("outdated") => Some(Box::new(Outdated)),
+        Some("remove") => Some(Box::new(Remove)),
+        Some("tree") => Some(Box::new(Tree)),
+        Some("update") => Some(Box::new(Update)),
+        Some("vendor") => Some(Box::new(Vendor)),
         Some("new") => Some(Box::new(New)),
         Some("package") => Some(Box::new(Package)),
         Some("publish") => Some(Box::new(Publish)),
@@ -177,6 +218,7 @@ fn start_log(matches: &clap::ArgMatches) {
     stderrlog::new()
         .module(module_path!())
         .module("smaug")
+        .module("smaug_lib")
         .quiet(quiet)
         .verbosity(verbosity + 2)
         .timestamp(stderrlog::Timestamp::Off)
@@ -0,0 +1,40 @@
+pub fn format(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+
+    for row in rows.iter() {
+        for (index, cell) in row.iter().enumerate() {
+            widths[index] = widths[index].max(cell.chars().count());
+        }
+    }
+
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    let mut lines = vec![format_row(&headers, &widths)];
+
+    for row in rows.iter() {
+        lines.push(format_row(row, &widths));
+    }
+
+    lines.join("\n")
+}
+
+fn format_row(row: &[String], widths: &[usize]) -> String {
+    row.iter()
+        .enumerate()
+        .map(|(index, cell)| format!("{:width$}", cell, width = widths[index]))
+        .collect::<Vec<String>>()
+        .join("  ")
+        .trim_end()
+        .to_string()
+}
+
+pub fn format_version(version: Option<&str>, commit: Option<&str>) -> String {
+    match (version, commit) {
+        (None, _) => "-".to_string(),
+        (Some(version), None) => version.to_string(),
+        (Some(version), Some(commit)) => format!("{} ({})", version, short_commit(commit)),
+    }
+}
+
+pub fn short_commit(commit: &str) -> String {
+    commit[..commit.len().min(7)].to_string()
+}
@@ -0,0 +1,115 @@
+use crate::smaug;
+use crate::util::digest;
+use log::*;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+
+static LOCKED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static RELEASED: Condvar = Condvar::new();
+
+/// Holds a cache entry for one thread until it is dropped.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let mut locked = LOCKED.lock().expect("Cache lock poisoned");
+        locked.retain(|path| path != &self.path);
+        RELEASED.notify_all();
+    }
+}
+
+/// Waits until no other thread is writing to a cache entry, then claims it.
+pub fn lock(path: &Path) -> Lock {
+    let mut locked = LOCKED.lock().expect("Cache lock poisoned");
+
+    while locked.iter().any(|locked| locked == path) {
+        locked = RELEASED.wait(locked).expect("Cache lock poisoned");
+    }
+
+    locked.push(path.to_path_buf());
+
+    Lock {
+        path: path.to_path_buf(),
+    }
+}
+
+/// Where a git repository is fetched to. Every package from the same repository shares it.
+pub fn git_dir(repo: &str) -> PathBuf {
+    smaug::cache_dir().join("git").join("db").join(key(repo))
+}
+
+/// Where the files of a single commit of a git repository are checked out.
+pub fn git_checkout_dir(repo: &str, commit: &str) -> PathBuf {
+    smaug::cache_dir()
+        .join("git")
+        .join("checkouts")
+        .join(key(repo))
+        .join(commit)
+}
+
+/// Where the file at a URL is downloaded to.
+pub fn download_path(url: &str) -> PathBuf {
+    smaug::cache_dir().join("downloads").join(key(url))
+}
+
+/// Where an archive is extracted to, keyed by the digest of the archive itself.
+pub fn extracted_dir(archive: &Path) -> io::Result<PathBuf> {
+    let digest = digest::file(archive)?;
+
+    Ok(smaug::cache_dir().join("extracted").join(key(&digest)))
+}
+
+/// Downloads a URL into the cache and returns the path of the downloaded file.
+/// A previous download is reused unless `refresh` is set, and always when offline.
+pub fn download(url: &str, refresh: bool) -> io::Result<PathBuf> {
+    let path = download_path(url);
+    let _lock = lock(&path);
+
+    if path.is_file() && (!refresh || smaug::offline()) {
+        trace!("Using cached download of {} at {}", url, path.display());
+        return Ok(path);
+    }
+
+    if smaug::offline() {
+        return Err(not_cached(url));
+    }
+
+    trace!("Downloading {} to {}", url, path.display());
+    let mut response = match reqwest::blocking::get(url) {
+        Ok(response) if response.status().is_success() => response,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Couldn't download {}", url),
+            ))
+        }
+    };
+
+    // Download next to the final location so an interrupted download is never mistaken for a complete one.
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let partial = path.with_extension("part");
+    let mut file = File::create(&partial)?;
+    io::copy(&mut response, &mut file)?;
+    std::fs::rename(&partial, &path)?;
+
+    Ok(path)
+}
+
+pub fn not_cached(identity: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{} is not in the cache. Run again without --offline to download it.",
+            identity
+        ),
+    )
+}
+
+fn key(identity: &str) -> String {
+    digest::string(identity)[..16].to_string()
+}
@@ -1,3 +1,4 @@
+use crate::util::digest;
 use derive_more::Display;
 use derive_more::Error;
 use linked_hash_map::LinkedHashMap;
@@ -11,6 +12,7 @@ use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use dunce;
@@ -23,6 +25,14 @@ pub struct Config {
     pub itch: Option<Itch>,
     #[serde(default)]
     pub dependencies: LinkedHashMap<String, DependencyOptions>,
+    #[serde(default, rename = "dev-dependencies")]
+    pub dev_dependencies: LinkedHashMap<String, DependencyOptions>,
+    #[serde(default)]
+    pub patch: Patches,
+    #[serde(default, rename = "install-overrides")]
+    pub install_overrides: InstallOverrides,
+    #[serde(default)]
+    pub registries: Registries,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -55,40 +65,126 @@ pub struct Project {
     pub compile_ruby: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DragonRuby {
     pub version: String,
     pub edition: String,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Registry {
+    pub url: String,
+}
+
+pub type Registries = LinkedHashMap<String, Registry>;
+
+/// Which package gets to install a file that more than one package, or the project
+/// itself, wants to put there.
+pub type InstallOverrides = LinkedHashMap<RelativePathBuf, String>;
+
+/// Packages to install from somewhere else wherever they appear in the dependency graph.
+pub type Patches = LinkedHashMap<String, DependencyOptions>;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Itch {
     pub url: String,
     pub username: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
 pub enum DependencyOptions {
     Dir {
         dir: PathBuf,
     },
     File {
         file: PathBuf,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        checksum: Option<String>,
     },
     Git {
+        #[serde(skip_serializing_if = "Option::is_none")]
         branch: Option<String>,
         repo: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
         rev: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         tag: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<RelativePathBuf>,
     },
     Registry {
         version: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        registry: Option<String>,
     },
     Url {
         url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        checksum: Option<String>,
     },
 }
 
+impl DependencyOptions {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DependencyOptions::Dir { .. } => "dir",
+            DependencyOptions::File { .. } => "file",
+            DependencyOptions::Git { .. } => "git",
+            DependencyOptions::Registry { .. } => "registry",
+            DependencyOptions::Url { .. } => "url",
+        }
+    }
+
+    /// Registry packages and git branches can move forward without Smaug.toml changing.
+    pub fn is_updatable(&self) -> bool {
+        match self {
+            DependencyOptions::Registry { .. } => true,
+            DependencyOptions::Git { tag, rev, .. } => tag.is_none() && rev.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Resolves relative directory and file dependencies declared by a package against
+    /// its directory.
+    pub fn relative_to(&self, dir: &Path) -> DependencyOptions {
+        match self {
+            DependencyOptions::Dir { dir: path } if path.is_relative() => DependencyOptions::Dir {
+                dir: canonical(dir.join(path)),
+            },
+            DependencyOptions::File {
+                file: path,
+                checksum,
+            } if path.is_relative() => DependencyOptions::File {
+                file: canonical(dir.join(path)),
+                checksum: checksum.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Whether a path stays below the directory it is joined to, like a package's `path`
+/// has to stay inside its repository.
+fn is_inside(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(..) | Component::CurDir))
+}
+
+/// Whether a path was written starting from the current directory, like `../base`.
+fn is_explicitly_relative(path: &str) -> bool {
+    matches!(
+        Path::new(path).components().next(),
+        Some(Component::CurDir) | Some(Component::ParentDir)
+    )
+}
+
+/// Spells a path the same way however it was written, so it always gets the same id.
+fn canonical(path: PathBuf) -> PathBuf {
+    dunce::canonicalize(&path).unwrap_or(path)
+}
+
 #[derive(Debug, Display, Error)]
 pub enum Error {
     #[display(fmt = "Could not find Smaug.toml at {}", "path.display()")]
@@ -117,7 +213,7 @@ pub fn load<P: AsRef<Path>>(path: &P) -> Result<Config, Error> {
         return Err(Error::FileNotFound { path });
     }
 
-    std::env::set_current_dir(&path.parent().unwrap()).unwrap();
+    std::env::set_current_dir(path.parent().unwrap()).unwrap();
     let contents = std::fs::read_to_string(path.clone()).expect("Could not read Smaug.toml");
     from_str(&contents, &path)
 }
@@ -164,6 +260,7 @@ impl<'de> Deserialize<'de> for DependencyOptions {
                 if VersionReq::parse(value).is_ok() {
                     Ok(DependencyOptions::Registry {
                         version: value.to_string(),
+                        registry: None,
                     })
                 } else if let Some("git") = path.extension().and_then(|str| str.to_str()) {
                     Ok(DependencyOptions::Git {
@@ -171,18 +268,24 @@ impl<'de> Deserialize<'de> for DependencyOptions {
                         branch: None,
                         rev: None,
                         tag: None,
+                        path: None,
                     })
                 } else if path.is_dir() {
-                    let canonical =
-                        dunce::canonicalize(path.clone()).expect("Could not find path.");
-                    Ok(DependencyOptions::Dir { dir: canonical })
+                    Ok(DependencyOptions::Dir {
+                        dir: canonical(path),
+                    })
                 } else if path.is_file() {
                     Ok(DependencyOptions::File {
-                        file: path.to_path_buf(),
+                        file: canonical(path),
+                        checksum: None,
                     })
+                } else if is_explicitly_relative(value) {
+                    // A package's sibling directory, resolved against the package later.
+                    Ok(DependencyOptions::Dir { dir: path })
                 } else if let Ok(_url) = url::Url::parse(value) {
                     Ok(DependencyOptions::Url {
                         url: value.to_string(),
+                        checksum: None,
                     })
                 } else {
                     Err(de::Error::invalid_value(
@@ -204,6 +307,9 @@ impl<'de> Deserialize<'de> for DependencyOptions {
                 let mut file: Option<String> = None;
                 let mut version: Option<String> = None;
                 let mut url: Option<String> = None;
+                let mut checksum: Option<String> = None;
+                let mut path: Option<RelativePathBuf> = None;
+                let mut registry: Option<String> = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -215,29 +321,74 @@ impl<'de> Deserialize<'de> for DependencyOptions {
                         "file" => file = Some(map.next_value()?),
                         "version" => version = Some(map.next_value()?),
                         "url" => url = Some(map.next_value()?),
+                        "checksum" => checksum = Some(map.next_value()?),
+                        "path" => {
+                            let value: String = map.next_value()?;
+
+                            if !is_inside(&value) {
+                                return Err(de::Error::invalid_value(
+                                    de::Unexpected::Str(&value),
+                                    &"a relative path inside the repository",
+                                ));
+                            }
+
+                            path = Some(RelativePathBuf::from(value));
+                        }
+                        "registry" => registry = Some(map.next_value()?),
                         _ => unreachable!(),
                     }
                 }
 
+                if let Some(checksum) = &checksum {
+                    if !digest::is_checksum(checksum) {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Str(checksum),
+                            &"a checksum starting with blake2b: or sha256:",
+                        ));
+                    }
+                }
+
+                // Only archives have a checksum to check.
+                let takes_checksum = repo.is_none()
+                    && dir.is_none()
+                    && (file.is_some() || version.is_none() && url.is_some());
+
+                if checksum.is_some() && !takes_checksum {
+                    return Err(de::Error::custom(
+                        "checksum can only be used with url and file dependencies",
+                    ));
+                }
+
+                if let Some(version) = &version {
+                    if VersionReq::parse(version).is_err() {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Str(version),
+                            &"a version requirement like \"1.2\" or \">= 1.0, < 2.0\"",
+                        ));
+                    }
+                }
+
                 if let Some(repo) = repo {
                     Ok(DependencyOptions::Git {
                         repo,
                         branch,
                         tag,
                         rev,
+                        path,
                     })
                 } else if let Some(dir) = dir {
                     Ok(DependencyOptions::Dir {
-                        dir: Path::new(&dir).to_path_buf(),
+                        dir: canonical(PathBuf::from(dir)),
                     })
                 } else if let Some(file) = file {
                     Ok(DependencyOptions::File {
-                        file: Path::new(&file).to_path_buf(),
+                        file: canonical(PathBuf::from(file)),
+                        checksum,
                     })
                 } else if let Some(version) = version {
-                    Ok(DependencyOptions::Registry { version })
+                    Ok(DependencyOptions::Registry { version, registry })
                 } else if let Some(url) = url {
-                    Ok(DependencyOptions::Url { url })
+                    Ok(DependencyOptions::Url { url, checksum })
                 } else {
                     Err(de::Error::invalid_value(
                         de::Unexpected::Map,
@@ -250,3 +401,99 @@ impl<'de> Deserialize<'de> for DependencyOptions {
         deserializer.deserialize_any(DependencyOptionsVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependency(toml: &str) -> Result<DependencyOptions, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct Dependencies {
+            dependency: DependencyOptions,
+        }
+
+        toml::from_str::<Dependencies>(toml).map(|dependencies| dependencies.dependency)
+    }
+
+    #[test]
+    fn a_relative_string_is_a_directory_even_before_it_exists() {
+        assert_eq!(
+            dependency(r#"dependency = "../smaug-missing-package""#).unwrap(),
+            DependencyOptions::Dir {
+                dir: PathBuf::from("../smaug-missing-package")
+            }
+        );
+    }
+
+    #[test]
+    fn checksums_are_only_accepted_for_url_and_file_dependencies() {
+        let checksum = "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+
+        for options in [
+            r#"repo = "https://github.com/example/draco.git""#,
+            r#"dir = "draco""#,
+            r#"version = "1.0""#,
+        ]
+        .iter()
+        {
+            let toml = format!(
+                r#"dependency = {{ {}, checksum = "{}" }}"#,
+                options, checksum
+            );
+            let error = dependency(&toml).unwrap_err().to_string();
+
+            assert!(
+                error.contains("checksum can only be used with url and file dependencies"),
+                "{}",
+                error
+            );
+        }
+
+        let toml = format!(
+            r#"dependency = {{ url = "https://example.com/draco.zip", checksum = "{}" }}"#,
+            checksum
+        );
+        assert_eq!(
+            dependency(&toml).unwrap(),
+            DependencyOptions::Url {
+                url: "https://example.com/draco.zip".to_string(),
+                checksum: Some(checksum.to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn relative_dirs_and_files_are_resolved_against_the_package() {
+        let package_dir = Path::new("/packages/top");
+
+        assert_eq!(
+            DependencyOptions::Dir {
+                dir: PathBuf::from("vendor/base")
+            }
+            .relative_to(package_dir),
+            DependencyOptions::Dir {
+                dir: package_dir.join("vendor/base")
+            }
+        );
+        assert_eq!(
+            DependencyOptions::File {
+                file: PathBuf::from("base.zip"),
+                checksum: None
+            }
+            .relative_to(package_dir),
+            DependencyOptions::File {
+                file: package_dir.join("base.zip"),
+                checksum: None
+            }
+        );
+    }
+
+    #[test]
+    fn absolute_paths_are_left_alone() {
+        let options = DependencyOptions::Dir {
+            dir: std::env::temp_dir(),
+        };
+
+        assert_eq!(options.relative_to(Path::new("/packages/top")), options);
+    }
+}
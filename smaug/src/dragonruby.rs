@@ -1,4 +1,5 @@
-use crate::{config::Config, smaug};
+use crate::util::archive;
+use crate::{config, config::Config, smaug};
 use derive_more::Display;
 use derive_more::Error;
 use log::*;
@@ -59,8 +60,8 @@ pub fn new<P: AsRef<Path>>(path: &P) -> DragonRubyResult {
 
     if dragonruby_path.is_dir() {
         parse_dragonruby_dir(dragonruby_path)
-    } else if zip_extensions::is_zip(&dragonruby_path.to_path_buf()) {
-        parse_dragonruby_zip(dragonruby_path)
+    } else if archive::is_archive(dragonruby_path) {
+        parse_dragonruby_archive(dragonruby_path)
     } else {
         Err(DragonRubyError::DragonRubyNotFound {
             path: dragonruby_path.to_path_buf(),
@@ -106,16 +107,23 @@ pub fn latest() -> DragonRubyResult {
     }
 }
 
+impl Edition {
+    /// Reads the `edition` from a `[dragonruby]` section. Anything unknown is Standard.
+    pub fn parse(edition: &str) -> Edition {
+        if edition == "pro" {
+            Edition::Pro
+        } else if edition == "indie" {
+            Edition::Indie
+        } else {
+            Edition::Standard
+        }
+    }
+}
+
 pub fn configured_version(config: &Config) -> Option<DragonRuby> {
     let version = VersionReq::parse(config.dragonruby.version.as_str())
         .expect("Not a valid DragonRuby version.");
-    let edition = if config.dragonruby.edition == "pro" {
-        Edition::Pro
-    } else if config.dragonruby.edition == "indie" {
-        Edition::Indie
-    } else {
-        Edition::Standard
-    };
+    let edition = Edition::parse(config.dragonruby.edition.as_str());
 
     let mut installed = list_installed().expect("Could not list installed.");
     installed.sort_by(|a, b| a.version.partial_cmp(&b.version).unwrap());
@@ -126,18 +134,63 @@ pub fn configured_version(config: &Config) -> Option<DragonRuby> {
     matched.map(|dragonruby| dragonruby.to_owned())
 }
 
+/// Explains why a package made for `package` can't run on the project's `project`
+/// DragonRuby, or returns `None` when it can.
+pub fn incompatibility(
+    project: &config::DragonRuby,
+    package: &config::DragonRuby,
+) -> Option<String> {
+    if Edition::parse(package.edition.as_str()) > Edition::parse(project.edition.as_str()) {
+        return Some(format!(
+            "it needs the {} edition of DragonRuby, but your project uses the {} edition",
+            package.edition, project.edition
+        ));
+    }
+
+    // The project's version is the oldest DragonRuby it runs on. A package's bare
+    // version, like `2.0`, accepts any later release with the same major version.
+    let version = package.version.trim();
+    let required = if version.starts_with(|c: char| c.is_ascii_digit()) {
+        VersionReq::parse(format!("^{}", version).as_str())
+    } else {
+        VersionReq::parse(version)
+    };
+    let required = required.ok()?;
+    let pinned = pinned_version(project.version.as_str())?;
+
+    if required.matches(&pinned) {
+        None
+    } else {
+        Some(format!(
+            "it needs DragonRuby {}, but your project uses DragonRuby {}",
+            package.version, project.version
+        ))
+    }
+}
+
+/// Turns a version like `2.0` from `Smaug.toml` into a full version.
+fn pinned_version(version: &str) -> Option<SemVer> {
+    let version = version.trim().trim_start_matches(['=', '^', '~', 'v']);
+    let mut parts: Vec<&str> = version.split('.').collect();
+
+    if parts.len() > 3 || parts.iter().any(|part| part.parse::<u64>().is_err()) {
+        return None;
+    }
+
+    parts.resize(3, "0");
+    SemVer::parse(parts.join(".").as_str()).ok()
+}
+
 pub fn list_installed() -> io::Result<Vec<DragonRuby>> {
     let location = smaug::data_dir().join("dragonruby");
     fs::create_dir_all(location.as_path())?;
 
     let folders = fs::read_dir(location).expect("DragonRuby install folder not found.");
     let versions: Vec<DragonRuby> = folders
-        .map(|folder| {
+        .flat_map(|folder| {
             let path = folder.expect("Invalid folder");
             parse_dragonruby_dir(&path.path())
         })
-        .filter(|path| path.is_ok())
-        .map(|path| path.unwrap())
         .collect();
 
     Ok(versions)
@@ -179,12 +232,13 @@ pub fn dragonruby_publish_name() -> String {
     }
 }
 
-fn parse_dragonruby_zip(path: &Path) -> DragonRubyResult {
-    let cache = smaug::cache_dir();
-    trace!("Unzipping DragonRuby from {}", path.display());
+fn parse_dragonruby_archive(path: &Path) -> DragonRubyResult {
+    // Package downloads live in the cache as well, so only clear our own corner of it.
+    let cache = smaug::cache_dir().join("dragonruby");
+    trace!("Extracting DragonRuby from {}", path.display());
     rm_rf::ensure_removed(cache.clone()).expect("Couldn't clear cache");
-    zip_extensions::zip_extract(&path.to_path_buf(), &cache).expect("Could not extract zip");
-    trace!("Unzipped DragonRuby to {}", cache.display());
+    archive::extract(path, &cache).expect("Could not extract archive");
+    trace!("Extracted DragonRuby to {}", cache.display());
 
     parse_dragonruby_dir(&cache)
 }
@@ -317,3 +371,50 @@ impl Serialize for Version {
         serializer.serialize_str(format!("{}", self).as_str())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dragonruby(version: &str, edition: &str) -> config::DragonRuby {
+        config::DragonRuby {
+            version: version.to_string(),
+            edition: edition.to_string(),
+        }
+    }
+
+    #[test]
+    fn editions_are_ordered_standard_indie_pro() {
+        assert!(Edition::parse("standard") < Edition::parse("indie"));
+        assert!(Edition::parse("indie") < Edition::parse("pro"));
+        assert_eq!(Edition::parse("something else"), Edition::Standard);
+    }
+
+    #[test]
+    fn pinned_version_fills_in_missing_parts() {
+        assert_eq!(pinned_version("2"), SemVer::parse("2.0.0").ok());
+        assert_eq!(pinned_version("2.1"), SemVer::parse("2.1.0").ok());
+        assert_eq!(pinned_version("^2.1.3"), SemVer::parse("2.1.3").ok());
+        assert_eq!(pinned_version(">= 2"), None);
+        assert_eq!(pinned_version("latest"), None);
+    }
+
+    #[test]
+    fn packages_need_the_projects_edition_or_lower() {
+        let project = dragonruby("2.0", "standard");
+
+        assert!(incompatibility(&project, &dragonruby("2.0", "pro")).is_some());
+        assert!(incompatibility(&project, &dragonruby("2.0", "standard")).is_none());
+        assert!(incompatibility(&dragonruby("2.0", "pro"), &dragonruby("2.0", "indie")).is_none());
+    }
+
+    #[test]
+    fn packages_need_a_version_the_project_runs_on() {
+        let project = dragonruby("2.1", "standard");
+
+        assert!(incompatibility(&project, &dragonruby("3.0", "standard")).is_some());
+        assert!(incompatibility(&project, &dragonruby("2.5", "standard")).is_some());
+        assert!(incompatibility(&project, &dragonruby("2.0", "standard")).is_none());
+        assert!(incompatibility(&project, &dragonruby("2.1", "standard")).is_none());
+    }
+}
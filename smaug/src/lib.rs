@@ -4,13 +4,19 @@ extern crate regex;
 extern crate semver;
 extern crate shellexpand;
 
+pub mod cache;
 pub mod config;
 pub mod dependency;
 pub mod dragonruby;
 pub mod itch;
+pub mod lockfile;
+pub mod manifest;
 pub mod project;
+pub mod receipt;
+pub mod registry;
 pub mod resolver;
 pub mod smaug;
 pub mod source;
 pub mod sources;
+pub mod user_config;
 pub mod util;
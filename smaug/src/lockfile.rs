@@ -0,0 +1,212 @@
+use derive_more::Display;
+use derive_more::Error;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+static HEADER: &str = "# This file is automatically @generated by Smaug.
+# Do not manually edit this file. Run `smaug install` instead.
+
+";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub digest: String,
+    /// Only needed while developing the project, never shipped with it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dev: bool,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum Error {
+    #[display(
+        fmt = "Could not read Smaug.lock at {}: {}",
+        "path.display()",
+        "parent"
+    )]
+    ReadError {
+        path: PathBuf,
+        parent: std::io::Error,
+    },
+    #[display(
+        fmt = "Could not parse Smaug.lock at {}: {}",
+        "path.display()",
+        "parent"
+    )]
+    ParseError {
+        path: PathBuf,
+        parent: toml::de::Error,
+    },
+}
+
+pub fn load<P: AsRef<Path>>(path: &P) -> Result<Lockfile, Error> {
+    let path = path.as_ref();
+
+    if !path.is_file() {
+        return Ok(Lockfile::default());
+    }
+
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            return Err(Error::ReadError {
+                path: path.to_path_buf(),
+                parent: err,
+            })
+        }
+    };
+
+    match toml::from_str::<Lockfile>(contents.as_str()) {
+        Ok(lockfile) => Ok(lockfile.resolved_against(&base_dir(path))),
+        Err(err) => Err(Error::ParseError {
+            path: path.to_path_buf(),
+            parent: err,
+        }),
+    }
+}
+
+impl Lockfile {
+    pub fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: &P) -> std::io::Result<()> {
+        let lockfile = self.relative_to(&base_dir(path.as_ref()));
+        let contents = toml::to_string(&lockfile).expect("Could not serialize Smaug.lock");
+
+        std::fs::write(path, format!("{}{}", HEADER, contents))
+    }
+
+    /// Spells `dir` and `file` sources from `base`, so the lockfile means the same on
+    /// every machine the project is checked out on.
+    pub fn relative_to(&self, base: &Path) -> Lockfile {
+        self.map_paths(|path| match crate::util::dir::path_from(base, path) {
+            Some(relative) if relative.as_str().is_empty() => ".".to_string(),
+            Some(relative) => relative.to_string(),
+            None => path.display().to_string(),
+        })
+    }
+
+    /// Turns relative `dir` and `file` sources back into the canonical paths the
+    /// sources are identified by.
+    pub fn resolved_against(&self, base: &Path) -> Lockfile {
+        self.map_paths(|path| {
+            let path = base.join(path);
+
+            dunce::canonicalize(&path)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+    }
+
+    fn map_paths<F>(&self, map: F) -> Lockfile
+    where
+        F: Fn(&Path) -> String,
+    {
+        let packages = self
+            .packages
+            .iter()
+            .map(|package| {
+                let mut package = package.clone();
+
+                if let Some((kind, path)) = package.source.split_once('+') {
+                    if kind == "dir" || kind == "file" {
+                        package.source = format!("{}+{}", kind, map(Path::new(path)));
+                    }
+                }
+
+                package
+            })
+            .collect();
+
+        Lockfile { packages }
+    }
+}
+
+/// Paths in a lockfile are relative to the directory it is in.
+fn base_dir(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    dunce::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(source: &str) -> LockedPackage {
+        LockedPackage {
+            name: "draco".to_string(),
+            version: "1.0.0".to_string(),
+            source: source.to_string(),
+            commit: None,
+            digest: String::new(),
+            dev: false,
+        }
+    }
+
+    #[test]
+    fn dir_and_file_sources_are_written_relative_to_the_lockfile() {
+        let home = std::env::temp_dir().join("alice");
+        let project_dir = home.join("game");
+        let lockfile = Lockfile {
+            packages: vec![
+                locked(
+                    format!("dir+{}", project_dir.join("vendor").join("draco").display()).as_str(),
+                ),
+                locked(format!("file+{}", home.join("draco.zip").display()).as_str()),
+                locked("registry+^1.0"),
+            ],
+        };
+
+        let sources: Vec<String> = lockfile
+            .relative_to(&project_dir)
+            .packages
+            .into_iter()
+            .map(|package| package.source)
+            .collect();
+
+        assert_eq!(
+            sources,
+            vec!["dir+vendor/draco", "file+../draco.zip", "registry+^1.0"]
+        );
+    }
+
+    #[test]
+    fn relative_sources_are_read_back_as_canonical_paths() {
+        let project_dir =
+            std::env::temp_dir().join(format!("smaug-lockfile-{}", std::process::id()));
+        std::fs::create_dir_all(project_dir.join("vendor").join("draco")).unwrap();
+        let project_dir = dunce::canonicalize(&project_dir).unwrap();
+        let lockfile = Lockfile {
+            packages: vec![locked(
+                format!("dir+{}", project_dir.join("vendor").join("draco").display()).as_str(),
+            )],
+        };
+        let path = project_dir.join("Smaug.lock");
+
+        lockfile.write(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let loaded = load(&path).unwrap();
+        std::fs::remove_dir_all(&project_dir).unwrap();
+
+        assert!(written.contains("source = \"dir+vendor/draco\""));
+        assert_eq!(loaded.packages[0].source, lockfile.packages[0].source);
+    }
+}
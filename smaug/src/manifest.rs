@@ -0,0 +1,60 @@
+use relative_path::RelativePath;
+use relative_path::RelativePathBuf;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+static HEADER: &str = "# This file is automatically @generated by Smaug.
+# It lists the files Smaug installed into your project. Do not edit it.
+
+";
+
+/// Every file `smaug install` placed in the project from `[package.installs]`,
+/// with its digest at the time, so files that are no longer installed can be
+/// cleaned up unless they have been edited since.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    #[serde(default, rename = "file")]
+    pub files: Vec<InstalledFile>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InstalledFile {
+    pub path: RelativePathBuf,
+    pub digest: String,
+}
+
+pub fn path(destination: &Path) -> PathBuf {
+    destination.join("installed.toml")
+}
+
+pub fn load(destination: &Path) -> io::Result<Manifest> {
+    let path = path(destination);
+
+    if !path.is_file() {
+        return Ok(Manifest::default());
+    }
+
+    let contents = std::fs::read_to_string(&path)?;
+
+    toml::from_str(contents.as_str()).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not parse {}: {}", path.display(), err),
+        )
+    })
+}
+
+impl Manifest {
+    pub fn get(&self, path: &RelativePath) -> Option<&InstalledFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    pub fn write(&self, destination: &Path) -> io::Result<()> {
+        let contents = toml::to_string(self).expect("Could not serialize install manifest");
+
+        std::fs::write(path(destination), format!("{}{}", HEADER, contents))
+    }
+}
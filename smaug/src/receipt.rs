@@ -0,0 +1,49 @@
+use crate::config::DependencyOptions;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Records how an installed package got into `smaug/`, so a later install can tell
+/// whether it still matches what Smaug.toml asks for.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Receipt {
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub digest: String,
+    pub options: DependencyOptions,
+}
+
+/// Receipts are kept next to the packages rather than inside them so they don't
+/// change the package's digest.
+pub fn path(destination: &Path, name: &str) -> PathBuf {
+    destination.join(".receipts").join(format!("{}.toml", name))
+}
+
+pub fn load(destination: &Path, name: &str) -> Option<Receipt> {
+    let contents = std::fs::read_to_string(path(destination, name)).ok()?;
+
+    toml::from_str(contents.as_str()).ok()
+}
+
+pub fn remove(destination: &Path, name: &str) -> io::Result<()> {
+    let path = path(destination, name);
+
+    if path.is_file() {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+impl Receipt {
+    pub fn write(&self, destination: &Path, name: &str) -> io::Result<()> {
+        let path = path(destination, name);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        let contents = toml::to_string(self).expect("Could not serialize receipt");
+        std::fs::write(path, contents)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::config::Registries;
+use std::io;
+
+pub const DEFAULT_URL: &str = "https://api.smaug.dev";
+
+/// Finds the URL of a registry, or of the default registry when no name is given.
+///
+/// The `SMAUG_REGISTRY` environment variable (`SMAUG_REGISTRY_<NAME>` for a named
+/// registry) wins, followed by the project's `[registries]`, then the user's.
+/// A registry named `default` replaces the Smaug registry.
+pub fn url(name: Option<&str>, registries: &Registries) -> io::Result<String> {
+    let variable = match name {
+        None => "SMAUG_REGISTRY".to_string(),
+        Some(name) => format!("SMAUG_REGISTRY_{}", name.to_uppercase().replace('-', "_")),
+    };
+
+    if let Ok(url) = std::env::var(variable) {
+        return Ok(url.trim_end_matches('/').to_string());
+    }
+
+    let key = name.unwrap_or("default");
+
+    if let Some(registry) = registries.get(key) {
+        return Ok(registry.url.trim_end_matches('/').to_string());
+    }
+
+    if let Ok(user_config) = crate::user_config::load() {
+        if let Some(registry) = user_config.registries.get(key) {
+            return Ok(registry.url.trim_end_matches('/').to_string());
+        }
+    }
+
+    match name {
+        None => Ok(DEFAULT_URL.to_string()),
+        Some(name) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No registry named {} is configured. Add it to [registries] in Smaug.toml or your user configuration.",
+                name
+            ),
+        )),
+    }
+}
@@ -1,49 +1,807 @@
 use crate::dependency;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::receipt::Receipt;
+use crate::sources::registry_source::{self, Published};
+use crate::util::digest;
+use crate::util::dir;
+use crate::util::parallel;
 use crate::{config, source::Source};
-use config::{Config, DependencyOptions};
+use config::{Config, DependencyOptions, InstallOverrides, Patches, Registries};
 use dependency::Dependency;
+use globset::GlobBuilder;
+use linked_hash_map::LinkedHashMap;
 use log::*;
-use semver::VersionReq;
+use relative_path::{RelativePath, RelativePathBuf};
+use semver::{Version, VersionReq};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
 use std::path::PathBuf;
+use walkdir::WalkDir;
 
 #[derive(Clone, Default)]
 pub struct Resolver {
     pub requirements: Vec<Dependency>,
     pub source_map: HashMap<String, Box<dyn Source>>,
     pub installs: Vec<Install>,
-    pub requires: Vec<String>,
+    pub requires: Vec<Require>,
+    pub lockfile: Lockfile,
+    pub edges: Vec<Edge>,
+    pub registries: Registries,
+    pub patches: Patches,
+    pub install_overrides: InstallOverrides,
+    pub jobs: Option<usize>,
+    pub allow_incompatible: bool,
+    dragonruby: Option<config::DragonRuby>,
+    dev: HashSet<String>,
+    hints: Vec<Edge>,
+    versions: HashMap<String, Vec<Published>>,
+    registry_urls: HashMap<String, String>,
+}
+
+/// Sources that install already downloaded packages, with the version they were fetched for.
+type Prefetched = HashMap<String, (Option<Version>, Box<dyn Source>)>;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Edge {
+    pub dependent: Option<String>,
+    pub dependency: Dependency,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct Install {
+    pub package: String,
     pub from: PathBuf,
     pub to: PathBuf,
 }
 
+impl Install {
+    /// Turns an entry of `[package.installs]` into the files it copies. A directory
+    /// or glob keeps each file's path below it, so `"sprites/**/*.png" = "art/"`
+    /// copies `sprites/ui/button.png` to `art/ui/button.png`.
+    pub fn expand(
+        package: &str,
+        from: &RelativePath,
+        to: &RelativePath,
+        package_dir: &Path,
+        project_dir: &Path,
+    ) -> io::Result<Vec<Install>> {
+        let is_glob = is_pattern(from.as_str());
+
+        if !is_glob && !from.to_path(package_dir).is_dir() {
+            return Ok(vec![Install {
+                package: package.to_string(),
+                from: from.to_path(package_dir),
+                to: to.to_path(project_dir),
+            }]);
+        }
+
+        // Everything up to the first component with a wildcard is copied over as `to`.
+        let base = RelativePathBuf::from(
+            from.components()
+                .map(|component| component.as_str())
+                .take_while(|component| !is_pattern(component))
+                .collect::<Vec<&str>>()
+                .join("/"),
+        );
+        let matcher = if is_glob {
+            let glob = GlobBuilder::new(from.as_str())
+                .literal_separator(true)
+                .build()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+            Some(glob.compile_matcher())
+        } else {
+            None
+        };
+
+        let mut installs = vec![];
+        let base_dir = base.to_path(package_dir);
+
+        if !base_dir.is_dir() {
+            return Ok(installs);
+        }
+
+        for entry in WalkDir::new(&base_dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = entry?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let relative = entry.path().strip_prefix(package_dir).unwrap();
+            let relative = RelativePathBuf::from_path(relative)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+            if let Some(matcher) = &matcher {
+                if !matcher.is_match(relative.as_str()) {
+                    continue;
+                }
+            }
+
+            let below = relative.strip_prefix(&base).unwrap();
+            installs.push(Install {
+                package: package.to_string(),
+                from: entry.path().to_path_buf(),
+                to: to.join(below).to_path(project_dir),
+            });
+        }
+
+        Ok(installs)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Require {
+    pub package: String,
+    pub path: String,
+}
+
+// Each pass can only add constraints, so this is only reached by pathological graphs.
+const MAX_PASSES: usize = 10;
+
 impl Resolver {
     pub fn install(&mut self, destination: PathBuf) -> std::io::Result<Vec<Dependency>> {
         info!("Installing Dependencies\n");
 
-        let reqs = self.requirements.clone();
-        let sources = self.source_map.clone();
+        let initial = self.clone();
+
+        for _ in 0..MAX_PASSES {
+            let lockfile = self.install_pass(&destination)?;
+            let conflicts = self.unsatisfied(&lockfile)?;
+
+            if conflicts.is_empty() {
+                self.check_installs(&destination)?;
+                self.sort_requires();
+
+                let dev_only = self.dev_only();
+                self.lockfile = lockfile;
+
+                for package in self.lockfile.packages.iter_mut() {
+                    package.dev = dev_only.contains(&package.name);
+                }
+
+                for name in self.patches.keys() {
+                    if !self.source_map.contains_key(name) {
+                        warn!(
+                            "The patch for {} was not used, no package depends on it.",
+                            name
+                        );
+                    }
+                }
+
+                info!("");
+
+                return Ok(self.requirements.clone());
+            }
+
+            // A package discovered late constrained a registry package that was
+            // already installed. Start over, taking every constraint seen so far
+            // into account when picking versions.
+            info!("Re-resolving {}", conflicts.join(", "));
+            let mut hints = self.edges.clone();
+            hints.append(&mut self.hints.clone());
+            let versions = self.versions.clone();
+
+            *self = initial.clone();
+            self.hints = hints;
+            self.versions = versions;
+            self.lockfile = merge_lockfiles(lockfile, &initial.lockfile);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Could not settle on a set of versions that satisfies every dependency.",
+        ))
+    }
+
+    fn install_pass(&mut self, destination: &Path) -> io::Result<Lockfile> {
+        let mut lockfile = Lockfile::default();
+        let mut index = 0;
+        let mut fetched = 0;
+        let mut prefetched: Prefetched = HashMap::new();
+
+        // Installing a package can queue its own dependencies, so the list grows as we go.
+        while index < self.requirements.len() {
+            // Download everything queued so far at once. Placing files and queueing
+            // dependencies still happens one package at a time, in order.
+            if index == fetched {
+                let queued = self.requirements[index..].to_vec();
+                prefetched.extend(self.prefetch(&queued, destination)?);
+                fetched = self.requirements.len();
+            }
+
+            let dependency = &self.requirements[index].clone();
+            let source = &self.source_map.get(&dependency.name).unwrap().clone();
+            let package_dir = destination.join(&dependency.name);
+            let version = self.registry_version(dependency, source.as_ref(), destination)?;
+            let locked = self.lockfile.get(&dependency.name).filter(|locked| {
+                locked.source == source.id()
+                    && version
+                        .as_ref()
+                        .is_none_or(|version| locked.version == version.to_string())
+            });
+
+            let package = match locked {
+                Some(locked)
+                    if source.installed(dependency, destination)
+                        && digest::directory(&package_dir)? == locked.digest =>
+                {
+                    info!(
+                        "{} is already installed{}",
+                        dependency.name,
+                        self.patched(dependency)
+                    );
+                    locked.clone()
+                }
+                _ => {
+                    info!("Installing {}{}", dependency.name, self.patched(dependency));
+                    rm_rf::ensure_removed(&package_dir).expect("Couldn't remove directory");
+                    crate::receipt::remove(destination, &dependency.name)?;
+
+                    let installer = match prefetched.remove(&dependency.name) {
+                        Some((fetched_version, installer)) if fetched_version == version => {
+                            installer
+                        }
+                        _ => installer(source.as_ref(), locked, &version),
+                    };
+                    let resolved = installer.install(dependency, destination)?;
+                    let version = match resolved.version {
+                        Some(version) => version,
+                        None => package_version(&package_dir)?,
+                    };
+
+                    let package = LockedPackage {
+                        name: dependency.name.clone(),
+                        version,
+                        source: source.id(),
+                        commit: resolved.commit,
+                        digest: digest::directory(&package_dir)?,
+                        dev: false,
+                    };
+
+                    verify_locked(&package, locked)?;
+
+                    let receipt = Receipt {
+                        version: package.version.clone(),
+                        commit: package.commit.clone(),
+                        digest: package.digest.clone(),
+                        options: source.options(),
+                    };
+                    receipt.write(destination, &dependency.name)?;
+
+                    package
+                }
+            };
+
+            // Pinned, so a git package's own Smaug.toml is read from the commit that was installed.
+            let pinned = source.locked(&package);
+            lockfile.packages.push(package);
+            pinned.update_resolver(self, dependency, destination)?;
+            index += 1;
+        }
+
+        Ok(lockfile)
+    }
+
+    /// Fetches the queued packages that aren't installed yet on several threads,
+    /// returning sources that install them from the cache.
+    fn prefetch(&mut self, queued: &[Dependency], destination: &Path) -> io::Result<Prefetched> {
+        let jobs = self.jobs.unwrap_or_else(parallel::default_jobs);
+
+        // Picking a registry version needs the published versions first, unless the
+        // locked version is installed and can be kept.
+        let unlisted: Vec<(String, String)> = queued
+            .iter()
+            .filter(|dependency| !self.versions.contains_key(&dependency.name))
+            .filter(|dependency| {
+                let source = self.source_map.get(&dependency.name).unwrap();
+
+                !matches!(
+                    self.installed_version(dependency, source.as_ref(), destination),
+                    Ok(Some(..))
+                )
+            })
+            .filter_map(|dependency| {
+                self.registry_urls
+                    .get(&dependency.name)
+                    .map(|url| (dependency.name.clone(), url.clone()))
+            })
+            .collect();
+        let listed = parallel::map(jobs, unlisted, |(name, url)| {
+            registry_source::fetch_versions(url.as_str(), name.as_str())
+                .map(|versions| (name, versions))
+        });
+
+        for result in listed {
+            let (name, versions) = result?;
+            self.versions.insert(name, versions);
+        }
+
+        let mut fetches = vec![];
+
+        for dependency in queued.iter() {
+            let source = self.source_map.get(&dependency.name).unwrap().clone();
+            // Conflicts are reported when the package is installed.
+            let version = match self.registry_version(dependency, source.as_ref(), destination) {
+                Ok(version) => version,
+                Err(..) => continue,
+            };
+            let locked = self.lockfile.get(&dependency.name).filter(|locked| {
+                locked.source == source.id()
+                    && version
+                        .as_ref()
+                        .is_none_or(|version| locked.version == version.to_string())
+            });
+
+            if locked.is_some() && source.installed(dependency, destination) {
+                continue;
+            }
+
+            let installer = installer(source.as_ref(), locked, &version);
+            fetches.push((dependency.clone(), version, installer));
+        }
+
+        let fetched = parallel::map(jobs, fetches, |(dependency, version, installer)| {
+            let fetched = installer.fetch(&dependency)?;
+            info!("Fetched {}", dependency.name);
+
+            Ok((dependency.name, (version, fetched)))
+        });
+
+        fetched.into_iter().collect()
+    }
+
+    /// The version of a registry package to install, or `None` for other sources.
+    fn registry_version(
+        &mut self,
+        dependency: &Dependency,
+        source: &dyn Source,
+        destination: &Path,
+    ) -> io::Result<Option<Version>> {
+        if crate::source::kind(&source.id()) != "registry" {
+            return Ok(None);
+        }
+
+        match self.installed_version(dependency, source, destination)? {
+            Some(version) => Ok(Some(version)),
+            None => self.solve(&dependency.name).map(Some),
+        }
+    }
+
+    /// The locked version of a registry package when it is installed and still satisfies
+    /// every requirement on it. Keeping it doesn't need the published versions, so
+    /// installing works offline.
+    fn installed_version(
+        &self,
+        dependency: &Dependency,
+        source: &dyn Source,
+        destination: &Path,
+    ) -> io::Result<Option<Version>> {
+        let locked = match self.lockfile.get(&dependency.name) {
+            Some(locked) if locked.source == source.id() => locked,
+            _ => return Ok(None),
+        };
+        let version = match Version::parse(locked.version.as_str()) {
+            Ok(version) => version,
+            Err(..) => return Ok(None),
+        };
+        let constraints = self.constraints(&dependency.name)?;
+
+        if constraints
+            .iter()
+            .all(|(_, requirement)| requirement.matches(&version))
+            && source.installed(dependency, destination)
+        {
+            Ok(Some(version))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Picks the highest published version of a registry package that satisfies
+    /// every requirement placed on it, preferring the locked version when it still fits.
+    /// Yanked versions are only used when they are already locked.
+    fn solve(&mut self, name: &str) -> io::Result<Version> {
+        let constraints = self.constraints(name)?;
+
+        if !self.versions.contains_key(name) {
+            let url = self
+                .registry_urls
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| crate::registry::DEFAULT_URL.to_string());
+            let versions = registry_source::fetch_versions(url.as_str(), name)?;
+            self.versions.insert(name.to_string(), versions);
+        }
+        let available = &self.versions[name];
+
+        let satisfies = |version: &Version| {
+            constraints
+                .iter()
+                .all(|(_, requirement)| requirement.matches(version))
+        };
+
+        let locked = self
+            .lockfile
+            .get(name)
+            .and_then(|locked| Version::parse(locked.version.as_str()).ok())
+            .filter(|version| {
+                available
+                    .iter()
+                    .any(|published| &published.version == version)
+                    && satisfies(version)
+            });
+
+        if let Some(locked) = locked {
+            return Ok(locked);
+        }
+
+        match registry_source::highest(available, satisfies) {
+            Some(version) => Ok(version),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                explain_conflict(name, &constraints, available),
+            )),
+        }
+    }
+
+    /// Makes sure no two packages install the same file, and that no package replaces
+    /// a file the project tracks in git, before anything is copied. `[install-overrides]`
+    /// settles a collision by naming the package that installs the file.
+    fn check_installs(&mut self, destination: &Path) -> io::Result<()> {
+        let project_dir = destination.parent().unwrap();
+        let installed = crate::manifest::load(destination)?;
+        let tracked = tracked_files(project_dir);
+        let mut claims: LinkedHashMap<RelativePathBuf, Vec<String>> = LinkedHashMap::new();
+
+        for install in self.installs.iter() {
+            let path = dir::relative(project_dir, &install.to)?;
+            let packages = claims.entry(path).or_insert_with(Vec::new);
+
+            if !packages.contains(&install.package) {
+                packages.push(install.package.clone());
+            }
+        }
+
+        let mut collisions = vec![];
+
+        for (path, packages) in claims.iter() {
+            let file = path.to_path(project_dir);
+            let mut claimants = packages.clone();
+
+            // Files Smaug installed before, or that already hold what would be installed,
+            // are safe to write over.
+            if tracked.contains(path) && installed.get(path).is_none() {
+                let current = digest::file(&file).ok();
+                let unchanged = self
+                    .installs
+                    .iter()
+                    .filter(|install| install.to == file)
+                    .all(|install| {
+                        current.is_none() || digest::file(&install.from).ok() == current
+                    });
+
+                if !unchanged {
+                    claimants.insert(0, "your project".to_string());
+                }
+            }
+
+            if claimants.len() < 2 {
+                continue;
+            }
+
+            match self.install_overrides.get(path) {
+                Some(owner) if packages.contains(owner) => {
+                    debug!("{} installs {}, as configured", owner, path);
+                    self.installs
+                        .retain(|install| install.to != file || &install.package == owner);
+                }
+                _ => collisions.push((path, claimants, packages)),
+            }
+        }
+
+        let (example, _, packages) = match collisions.first() {
+            Some(collision) => collision,
+            None => return Ok(()),
+        };
+        let report: Vec<String> = collisions
+            .iter()
+            .map(|(path, claimants, _)| format!("  {} ({})", path, claimants.join(", ")))
+            .collect();
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "These files would be installed more than once:\n{}\n\n\
+                Choose the package that installs each one in Smaug.toml, for example:\n\n\
+                [install-overrides]\n\
+                \"{}\" = \"{}\"",
+                report.join("\n"),
+                example,
+                packages[0],
+            ),
+        ))
+    }
+
+    /// Orders packages so each one comes after the packages it depends on, keeping the
+    /// order they were declared in otherwise.
+    fn install_order(&self) -> Vec<String> {
+        let mut order: Vec<String> = vec![];
+
+        for dependency in self.requirements.iter() {
+            self.visit(&dependency.name, &mut order);
+        }
+
+        order
+    }
+
+    fn visit(&self, name: &str, order: &mut Vec<String>) {
+        if order.iter().any(|visited| visited == name) {
+            return;
+        }
+
+        // Cycles are rejected while resolving, so this always terminates. Marking the
+        // package first keeps it that way if one slips through.
+        order.push(name.to_string());
+        let position = order.len() - 1;
+
+        for edge in self.edges.iter() {
+            if edge.dependent.as_deref() == Some(name) {
+                self.visit(&edge.dependency.name, order);
+            }
+        }
+
+        let name = order.remove(position);
+        order.push(name);
+    }
+
+    /// Puts the requires of every package after those of its dependencies. A package's
+    /// own requires stay in the order it lists them.
+    fn sort_requires(&mut self) {
+        let order = self.install_order();
+
+        self.requires.sort_by_key(|require| {
+            order
+                .iter()
+                .position(|name| *name == require.package)
+                .unwrap_or(order.len())
+        });
+    }
+
+    /// Packages that are only required, directly or not, by the project's dev-dependencies.
+    pub fn dev_only(&self) -> HashSet<String> {
+        let mut needed: HashSet<String> = HashSet::new();
+        let mut queue: Vec<&str> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.dependent.is_none() && !self.dev.contains(&edge.dependency.name))
+            .map(|edge| edge.dependency.name.as_str())
+            .collect();
+
+        while let Some(name) = queue.pop() {
+            if !needed.insert(name.to_string()) {
+                continue;
+            }
+
+            for edge in self.edges.iter() {
+                if edge.dependent.as_deref() == Some(name) {
+                    queue.push(edge.dependency.name.as_str());
+                }
+            }
+        }
+
+        self.requirements
+            .iter()
+            .filter(|dependency| !needed.contains(&dependency.name))
+            .map(|dependency| dependency.name.clone())
+            .collect()
+    }
+
+    /// Every version requirement placed on a package, along with who placed it.
+    fn constraints(&self, name: &str) -> io::Result<Vec<(String, VersionReq)>> {
+        let mut constraints: Vec<(String, VersionReq)> = vec![];
+
+        for edge in self.edges.iter().chain(self.hints.iter()) {
+            if edge.dependency.name != name {
+                continue;
+            }
+
+            let dependent = match &edge.dependent {
+                Some(dependent) => dependent.clone(),
+                None => "your project".to_string(),
+            };
+            let requirement = match VersionReq::parse(edge.dependency.version.as_str()) {
+                Ok(requirement) => requirement,
+                Err(..) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} requires {} {}, which is not a valid version requirement.",
+                            dependent, name, edge.dependency.version
+                        ),
+                    ))
+                }
+            };
+
+            if !constraints.contains(&(dependent.clone(), requirement.clone())) {
+                constraints.push((dependent, requirement));
+            }
+        }
+
+        Ok(constraints)
+    }
+
+    /// Registry packages whose installed version breaks a requirement found later on.
+    fn unsatisfied(&self, lockfile: &Lockfile) -> io::Result<Vec<String>> {
+        let mut unsatisfied = vec![];
+
+        for package in lockfile.packages.iter() {
+            if crate::source::kind(&package.source) != "registry" {
+                continue;
+            }
+
+            if let Ok(version) = Version::parse(package.version.as_str()) {
+                let constraints = self.constraints(&package.name)?;
+
+                if !constraints
+                    .iter()
+                    .all(|(_, requirement)| requirement.matches(&version))
+                {
+                    unsatisfied.push(package.name.clone());
+                }
+            }
+        }
+
+        Ok(unsatisfied)
+    }
+
+    pub fn add_dependency(
+        &mut self,
+        dependent: Option<&str>,
+        name: &str,
+        options: &DependencyOptions,
+    ) -> io::Result<()> {
+        // A patch replaces the package wherever it shows up in the graph.
+        let options = self.patches.get(name).unwrap_or(options).clone();
+        let options = &options;
 
-        for dependency in reqs.iter() {
-            let source = sources.get(&dependency.name).unwrap();
+        let version = match options {
+            DependencyOptions::Registry { version, .. } => version.clone(),
+            _ => VersionReq::any().to_string(),
+        };
 
-            if source.installed(dependency, &destination) {
-                info!("{} is already installed", dependency.name);
-            } else {
-                info!("Installing {}", dependency.name);
-                source.install(dependency, &destination)?;
+        debug!("{:?}", options);
+        let source = crate::source::from_dependency_options(options, &self.registries)?;
+        let dependency = Dependency {
+            name: name.to_string(),
+            version,
+        };
+
+        if let Some(dependent) = dependent {
+            if let Some(cycle) = self.find_path(name, dependent) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Dependency cycle detected: {} -> {}",
+                        cycle.join(" -> "),
+                        name
+                    ),
+                ));
             }
+        }
 
-            source.update_resolver(self, dependency, &destination);
+        self.edges.push(Edge {
+            dependent: dependent.map(|dependent| dependent.to_string()),
+            dependency: dependency.clone(),
+        });
+
+        match self.source_map.get(name) {
+            None => {
+                if let DependencyOptions::Registry { registry, .. } = options {
+                    let url = crate::registry::url(registry.as_deref(), &self.registries)?;
+                    self.registry_urls.insert(name.to_string(), url);
+                }
+
+                self.add_requirement(dependency);
+                self.add_source(name.to_string(), source);
+            }
+            Some(existing)
+                if existing.id() == source.id()
+                    || crate::source::kind(&existing.id()) == "registry"
+                        && crate::source::kind(&source.id()) == "registry" =>
+            {
+                info!(
+                    "{} is already required, skipping the duplicate from {}",
+                    name,
+                    dependent.unwrap_or("the project")
+                );
+            }
+            Some(existing) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is required from two different sources: {} and {}",
+                        name,
+                        existing.id(),
+                        source.id()
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notes when a package is installed from a `[patch]` instead of where it was declared.
+    fn patched(&self, dependency: &Dependency) -> String {
+        match self.patches.get(&dependency.name) {
+            Some(..) => {
+                let id = self.source_map.get(&dependency.name).unwrap().id();
+                format!(" from {} (patched)", id)
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Finds a chain of dependencies leading from `from` to `to`, if there is one.
+    fn find_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        if from == to {
+            return Some(vec![from.to_string()]);
         }
 
-        info!("");
+        let mut stack = vec![vec![from.to_string()]];
 
-        Ok(reqs)
+        while let Some(path) = stack.pop() {
+            let last = path.last().unwrap();
+
+            for edge in self.edges.iter() {
+                if edge.dependent.as_deref() != Some(last.as_str())
+                    || path.contains(&edge.dependency.name)
+                {
+                    continue;
+                }
+
+                let mut next = path.clone();
+                next.push(edge.dependency.name.clone());
+
+                if edge.dependency.name == to {
+                    return Some(next);
+                }
+
+                stack.push(next);
+            }
+        }
+
+        None
+    }
+
+    /// Checks that a package runs on the DragonRuby version and edition the project uses.
+    pub fn check_dragonruby(&self, name: &str, dragonruby: &config::DragonRuby) -> io::Result<()> {
+        let project = match &self.dragonruby {
+            Some(project) => project,
+            None => return Ok(()),
+        };
+
+        if let Some(reason) = crate::dragonruby::incompatibility(project, dragonruby) {
+            let message = format!("{} is not compatible with your project: {}.", name, reason);
+
+            if !self.allow_incompatible {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}\nPass --allow-incompatible to install it anyway.",
+                        message
+                    ),
+                ));
+            }
+
+            warn!("{}", message);
+        }
+
+        Ok(())
     }
 
     pub fn add_source(&mut self, name: String, source: Box<dyn Source>) {
@@ -55,27 +813,476 @@ impl Resolver {
     }
 }
 
-pub fn new_from_config(config: &Config) -> Resolver {
-    let mut resolver = Resolver::default();
+pub fn new_from_config(config: &Config) -> io::Result<Resolver> {
+    let mut resolver = Resolver {
+        registries: config.registries.clone(),
+        patches: config.patch.clone(),
+        install_overrides: config.install_overrides.clone(),
+        dragonruby: Some(config.dragonruby.clone()),
+        ..Resolver::default()
+    };
 
     for (name, dependency_options) in config.dependencies.iter() {
-        let name = String::from(name);
-        let version = match dependency_options {
-            DependencyOptions::Registry { version, .. } => version.clone(),
-            _ => VersionReq::any().to_string(),
+        resolver.add_dependency(None, name, dependency_options)?;
+    }
+
+    for (name, dependency_options) in config.dev_dependencies.iter() {
+        if !config.dependencies.contains_key(name) {
+            resolver.dev.insert(name.to_string());
+        }
+
+        resolver.add_dependency(None, name, dependency_options)?;
+    }
+
+    Ok(resolver)
+}
+
+/// The files in the project's git index, relative to the project.
+fn tracked_files(project_dir: &Path) -> HashSet<RelativePathBuf> {
+    let repo = match git2::Repository::discover(project_dir) {
+        Ok(repo) => repo,
+        Err(..) => return HashSet::new(),
+    };
+    let (workdir, index) = match (repo.workdir(), repo.index()) {
+        (Some(workdir), Ok(index)) => (workdir.to_path_buf(), index),
+        _ => return HashSet::new(),
+    };
+    let project_dir = dunce::canonicalize(project_dir).unwrap_or_else(|_| project_dir.into());
+    let workdir = dunce::canonicalize(&workdir).unwrap_or(workdir);
+
+    index
+        .iter()
+        .filter_map(|entry| {
+            let path = workdir.join(String::from_utf8_lossy(&entry.path).as_ref());
+            dir::relative(&project_dir, &path).ok()
+        })
+        .collect()
+}
+
+fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// The source that installs exactly what the lockfile or the version solver asked for.
+fn installer(
+    source: &dyn Source,
+    locked: Option<&LockedPackage>,
+    version: &Option<Version>,
+) -> Box<dyn Source> {
+    match (locked, version) {
+        (Some(locked), _) => source.locked(locked),
+        (None, Some(version)) => source.with_version(version),
+        (None, None) => source.clone_box(),
+    }
+}
+
+fn package_version(package_dir: &Path) -> io::Result<String> {
+    let config_path = package_dir.join("Smaug.toml");
+
+    match crate::config::load(&config_path) {
+        Ok(config) => match config.package {
+            Some(package) => Ok(package.version),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "No package configuration found in {}",
+                    config_path.display()
+                ),
+            )),
+        },
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+    }
+}
+
+fn explain_conflict(
+    name: &str,
+    constraints: &[(String, VersionReq)],
+    available: &[Published],
+) -> String {
+    let mut explanation = format!(
+        "Could not find a version of {} that satisfies every requirement:",
+        name
+    );
+
+    for (dependent, requirement) in constraints.iter() {
+        explanation
+            .push_str(format!("\n  * {} requires {} {}", dependent, name, requirement).as_str());
+    }
+
+    let mut available: Vec<Version> = available
+        .iter()
+        .filter(|published| !published.yanked)
+        .map(|published| published.version.clone())
+        .collect();
+    available.sort();
+    let available: Vec<String> = available
+        .iter()
+        .map(|version| version.to_string())
+        .collect();
+
+    explanation.push_str(format!("\nAvailable versions: {}", available.join(", ")).as_str());
+
+    explanation
+}
+
+fn merge_lockfiles(newer: Lockfile, older: &Lockfile) -> Lockfile {
+    let mut merged = newer;
+
+    for package in older.packages.iter() {
+        if merged.get(&package.name).is_none() {
+            merged.packages.push(package.clone());
+        }
+    }
+
+    merged
+}
+
+fn verify_locked(package: &LockedPackage, locked: Option<&LockedPackage>) -> io::Result<()> {
+    let locked = match locked {
+        Some(locked) => locked,
+        None => return Ok(()),
+    };
+
+    // Directory dependencies are local working copies and are expected to change.
+    if crate::source::kind(&package.source) == "dir" || package.digest == locked.digest {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} does not match Smaug.lock.\nExpected digest: {}\nActual digest: {}",
+            package.name, locked.digest, package.digest
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::registry_source::RegistrySource;
+
+    fn edge(dependent: Option<&str>, name: &str, version: &str) -> Edge {
+        Edge {
+            dependent: dependent.map(|dependent| dependent.to_string()),
+            dependency: Dependency {
+                name: name.to_string(),
+                version: version.to_string(),
+            },
+        }
+    }
+
+    fn published(version: &str, yanked: bool) -> Published {
+        Published {
+            version: Version::parse(version).unwrap(),
+            yanked,
+        }
+    }
+
+    fn registry_resolver(edges: Vec<Edge>, versions: Vec<Published>) -> Resolver {
+        let mut resolver = Resolver {
+            edges,
+            ..Resolver::default()
+        };
+        resolver.versions.insert("draco".to_string(), versions);
+
+        resolver
+    }
+
+    fn diamond() -> Resolver {
+        // The project requires `game`, which builds on `left` and `right`, which both
+        // build on `base`. `right` is also listed by the project, ahead of `game`.
+        let mut resolver = Resolver::default();
+
+        for name in ["right", "game", "left", "base"].iter() {
+            resolver.requirements.push(Dependency {
+                name: name.to_string(),
+                version: "*".to_string(),
+            });
+        }
+
+        resolver.edges = vec![
+            edge(None, "right", "*"),
+            edge(None, "game", "*"),
+            edge(Some("game"), "left", "*"),
+            edge(Some("game"), "right", "*"),
+            edge(Some("left"), "base", "*"),
+            edge(Some("right"), "base", "*"),
+        ];
+
+        resolver
+    }
+
+    fn require(package: &str, path: &str) -> Require {
+        Require {
+            package: package.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn install_order_puts_dependencies_first() {
+        assert_eq!(
+            diamond().install_order(),
+            vec!["base", "right", "left", "game"]
+        );
+    }
+
+    #[test]
+    fn sort_requires_keeps_each_packages_own_order() {
+        let mut resolver = diamond();
+        resolver.requires = vec![
+            require("right", "right.rb"),
+            require("game", "game/b.rb"),
+            require("game", "game/a.rb"),
+            require("left", "left.rb"),
+            require("base", "base/z.rb"),
+            require("base", "base/y.rb"),
+        ];
+
+        resolver.sort_requires();
+
+        let paths: Vec<&str> = resolver
+            .requires
+            .iter()
+            .map(|require| require.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "base/z.rb",
+                "base/y.rb",
+                "right.rb",
+                "left.rb",
+                "game/b.rb",
+                "game/a.rb",
+            ]
+        );
+    }
+
+    /// A package directory holding the given files, and an empty project next to it.
+    fn package_with(name: &str, files: &[&str]) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("smaug-{}-{}", name, std::process::id()));
+        rm_rf::ensure_removed(&root).unwrap();
+
+        let package_dir = root.join("package");
+        let project_dir = root.join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        for file in files {
+            let path = package_dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+
+        (package_dir, project_dir)
+    }
+
+    fn expand(from: &str, to: &str, package_dir: &Path, project_dir: &Path) -> Vec<String> {
+        Install::expand(
+            "sprites",
+            RelativePath::new(from),
+            RelativePath::new(to),
+            package_dir,
+            project_dir,
+        )
+        .unwrap()
+        .iter()
+        .map(|install| {
+            let from = install.from.strip_prefix(package_dir).unwrap();
+            let to = install.to.strip_prefix(project_dir).unwrap();
+
+            format!(
+                "{} -> {}",
+                RelativePathBuf::from_path(from).unwrap(),
+                RelativePathBuf::from_path(to).unwrap()
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn expand_keeps_paths_below_the_glob_base() {
+        let files = [
+            "sprites/top.png",
+            "sprites/ui/button.png",
+            "sprites/notes.txt",
+        ];
+        let (package_dir, project_dir) = package_with("expand-glob", &files);
+
+        assert_eq!(
+            expand("sprites/**/*.png", "art", &package_dir, &project_dir),
+            vec![
+                "sprites/top.png -> art/top.png",
+                "sprites/ui/button.png -> art/ui/button.png",
+            ]
+        );
+        assert_eq!(
+            expand("sprites/*.png", "art", &package_dir, &project_dir),
+            vec!["sprites/top.png -> art/top.png"]
+        );
+    }
+
+    #[test]
+    fn expand_copies_every_file_in_a_directory() {
+        let files = ["sounds/a.wav", "sounds/sfx/b.wav"];
+        let (package_dir, project_dir) = package_with("expand-dir", &files);
+
+        assert_eq!(
+            expand("sounds", "audio", &package_dir, &project_dir),
+            vec![
+                "sounds/a.wav -> audio/a.wav",
+                "sounds/sfx/b.wav -> audio/sfx/b.wav"
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_installs_a_single_file_where_it_is_told() {
+        let (package_dir, project_dir) = package_with("expand-file", &["lib/draco.rb"]);
+
+        assert_eq!(
+            expand("lib/draco.rb", "app/lib/ecs.rb", &package_dir, &project_dir),
+            vec!["lib/draco.rb -> app/lib/ecs.rb"]
+        );
+    }
+
+    #[test]
+    fn solve_picks_the_highest_version_every_requirement_allows() {
+        let mut resolver = registry_resolver(
+            vec![
+                edge(None, "draco", "^1.0"),
+                edge(Some("ecs"), "draco", ">= 1.1"),
+            ],
+            vec![
+                published("1.0.0", false),
+                published("1.1.0", false),
+                published("1.2.0", true),
+                published("2.0.0", false),
+            ],
+        );
+
+        assert_eq!(
+            resolver.solve("draco").unwrap(),
+            Version::parse("1.1.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn solve_keeps_the_locked_version_while_it_still_fits() {
+        let mut resolver = registry_resolver(
+            vec![edge(None, "draco", "^1.0")],
+            vec![published("1.0.0", false), published("1.1.0", false)],
+        );
+        resolver.lockfile.packages.push(LockedPackage {
+            name: "draco".to_string(),
+            version: "1.0.0".to_string(),
+            source: "registry+^1.0".to_string(),
+            commit: None,
+            digest: String::new(),
+            dev: false,
+        });
+
+        assert_eq!(
+            resolver.solve("draco").unwrap(),
+            Version::parse("1.0.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn an_installed_locked_version_is_kept_without_listing_versions() {
+        let destination =
+            std::env::temp_dir().join(format!("smaug-resolver-locked-{}", std::process::id()));
+        let package_dir = destination.join("draco");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(package_dir.join("draco.rb"), "").unwrap();
+
+        // Nothing listens here, so listing versions would fail.
+        let source = RegistrySource {
+            url: "http://127.0.0.1:9/".to_string(),
+            registry: None,
+            version: "^1.0".to_string(),
+            resolved: None,
+            rev: None,
         };
+        let digest = digest::directory(&package_dir).unwrap();
+        Receipt {
+            version: "1.0.0".to_string(),
+            commit: None,
+            digest: digest.clone(),
+            options: source.options(),
+        }
+        .write(&destination, "draco")
+        .unwrap();
 
-        debug!("{:?}", dependency_options);
-        let source = crate::source::from_dependency_options(dependency_options)
-            .expect("could not create source");
         let dependency = Dependency {
-            name: name.clone(),
-            version,
+            name: "draco".to_string(),
+            version: "^1.0".to_string(),
+        };
+        let mut resolver = Resolver {
+            edges: vec![edge(None, "draco", "^1.0")],
+            ..Resolver::default()
         };
+        resolver
+            .registry_urls
+            .insert("draco".to_string(), source.url.clone());
+        resolver
+            .source_map
+            .insert("draco".to_string(), Box::new(source.clone()));
+        resolver.lockfile.packages.push(LockedPackage {
+            name: "draco".to_string(),
+            version: "1.0.0".to_string(),
+            source: source.id(),
+            commit: None,
+            digest,
+            dev: false,
+        });
+
+        let prefetched = resolver
+            .prefetch(std::slice::from_ref(&dependency), &destination)
+            .unwrap();
+        let version = resolver
+            .registry_version(&dependency, &source, &destination)
+            .unwrap();
+        std::fs::remove_dir_all(&destination).unwrap();
 
-        resolver.add_requirement(dependency.clone());
-        resolver.add_source(dependency.name, source);
+        assert!(prefetched.is_empty());
+        assert_eq!(version, Some(Version::parse("1.0.0").unwrap()));
+        assert!(resolver.versions.is_empty());
     }
 
-    resolver
+    #[test]
+    fn solve_explains_conflicting_requirements() {
+        let mut resolver = registry_resolver(
+            vec![
+                edge(None, "draco", "^1.0"),
+                edge(Some("ecs"), "draco", "^2.0"),
+            ],
+            vec![
+                published("1.0.0", false),
+                published("2.0.0", false),
+                published("2.1.0", true),
+            ],
+        );
+
+        let err = resolver.solve("draco").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Could not find a version of draco that satisfies every requirement:\n  \
+             * your project requires draco >=1.0.0, <2.0.0\n  \
+             * ecs requires draco >=2.0.0, <3.0.0\n\
+             Available versions: 1.0.0, 2.0.0"
+        );
+    }
+
+    #[test]
+    fn invalid_requirements_are_an_error() {
+        let mut resolver = registry_resolver(
+            vec![edge(None, "draco", "latest")],
+            vec![published("1.0.0", false)],
+        );
+
+        assert!(resolver.solve("draco").is_err());
+    }
 }
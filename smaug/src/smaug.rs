@@ -1,12 +1,28 @@
 use directories::ProjectDirs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
 
 pub fn data_dir() -> PathBuf {
-    return project_dirs().data_dir().to_path_buf();
+    project_dirs().data_dir().to_path_buf()
 }
 
 pub fn cache_dir() -> PathBuf {
-    return project_dirs().cache_dir().to_path_buf();
+    project_dirs().cache_dir().to_path_buf()
+}
+
+pub fn config_dir() -> PathBuf {
+    project_dirs().config_dir().to_path_buf()
+}
+
+/// When offline, packages are only installed from the cache and nothing is downloaded.
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed)
 }
 
 fn project_dirs() -> ProjectDirs {
@@ -1,20 +1,71 @@
 use url_source::UrlSource;
 
+use crate::config::{Config, DependencyOptions, Registries};
+use crate::lockfile::LockedPackage;
 use crate::resolver::Install;
+use crate::resolver::Require;
 use crate::sources::file_source::FileSource;
+use crate::sources::git_source::GitSource;
 use crate::sources::registry_source::RegistrySource;
-use crate::{config::DependencyOptions, sources::git_source::GitSource};
+use crate::util::digest;
 use crate::{dependency::Dependency, sources::url_source};
 use crate::{resolver::Resolver, sources::dir_source::DirSource};
 use log::*;
+use semver::Version;
 use std::path::Path;
 
-pub trait Source: SourceClone {
-    fn install(&self, dependency: &Dependency, path: &Path) -> std::io::Result<()>;
+#[derive(Clone, Debug, Default)]
+pub struct Resolved {
+    pub commit: Option<String>,
+    pub version: Option<String>,
+}
+
+pub trait Source: SourceClone + Send + Sync {
+    fn install(&self, dependency: &Dependency, path: &Path) -> std::io::Result<Resolved>;
+
+    fn id(&self) -> String;
+
+    /// The Smaug.toml options that describe this source.
+    fn options(&self) -> DependencyOptions;
+
+    /// Downloads everything `install` needs into the cache. Returns a source that
+    /// installs from what was downloaded without touching the network again.
+    fn fetch(&self, _dependency: &Dependency) -> std::io::Result<Box<dyn Source>> {
+        Ok(self.clone_box())
+    }
+
+    fn locked(&self, _locked: &LockedPackage) -> Box<dyn Source> {
+        self.clone_box()
+    }
 
+    fn with_version(&self, _version: &Version) -> Box<dyn Source> {
+        self.clone_box()
+    }
+
+    /// Whether the package in `destination` was installed from these exact options
+    /// and hasn't been changed since.
     fn installed(&self, dependency: &Dependency, destination: &Path) -> bool {
-        let destination = destination.join(dependency.clone().name);
-        destination.exists()
+        let package_dir = destination.join(dependency.clone().name);
+
+        match crate::receipt::load(destination, &dependency.name) {
+            Some(receipt) => {
+                package_dir.is_dir()
+                    && receipt.options == self.options()
+                    && digest::directory(&package_dir).ok() == Some(receipt.digest)
+            }
+            None => false,
+        }
+    }
+
+    /// Reads the package's Smaug.toml from where the package came from, so the relative
+    /// paths in it point where its author meant them to. Archives only have the copy
+    /// that was installed.
+    fn package_config(
+        &self,
+        dependency: &Dependency,
+        destination: &Path,
+    ) -> std::io::Result<Config> {
+        package_config(&destination.join(&dependency.name))
     }
 
     fn update_resolver(
@@ -22,24 +73,19 @@ pub trait Source: SourceClone {
         resolver: &mut Resolver,
         dependency: &Dependency,
         destination: &Path,
-    ) {
+    ) -> std::io::Result<()> {
         let project_dir = destination.parent().unwrap();
+        let config = self.package_config(dependency, destination)?;
         let destination = destination.join(dependency.clone().name);
-        let config_path = destination.join("Smaug.toml");
-        let config = crate::config::load(&config_path).expect("Could not find Smaug.toml");
         debug!("Package config: {:?}", config);
         let package = config.package.expect("No package configuration found.");
+        resolver.check_dragonruby(&dependency.name, &config.dragonruby)?;
 
         for (from, to) in package.installs {
-            let install_source = from.to_path(destination.as_path());
-            let install_destination = to.to_path(project_dir);
+            let mut installs =
+                Install::expand(&dependency.name, &from, &to, &destination, project_dir)?;
 
-            let install = Install {
-                from: install_source,
-                to: install_destination,
-            };
-
-            resolver.installs.push(install);
+            resolver.installs.append(&mut installs);
         }
 
         let mut requires = package
@@ -49,49 +95,93 @@ pub trait Source: SourceClone {
                 let package_file = require.to_path(destination.clone());
                 trace!("Checking package file {:?}", package_file);
 
-                if package_file.exists() {
+                let path = if package_file.exists() {
                     trace!("package file exists");
                     format!("smaug/{}/{}", dependency.name, require)
                 } else {
                     trace!("package file does not exists");
                     require.to_string()
+                };
+
+                Require {
+                    package: dependency.name.clone(),
+                    path,
                 }
             })
             .collect();
         resolver.requires.append(&mut requires);
+
+        for (name, options) in config.dependencies.iter() {
+            resolver.add_dependency(Some(dependency.name.as_str()), name, options)?;
+        }
+
+        Ok(())
     }
 }
 
+/// Loads the Smaug.toml in `package_dir`, resolving relative `dir` and `file`
+/// dependencies against it.
+pub fn package_config(package_dir: &Path) -> std::io::Result<Config> {
+    let mut config = crate::config::load(&package_dir.join("Smaug.toml"))
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+
+    for (_, options) in config.dependencies.iter_mut() {
+        *options = options.relative_to(package_dir);
+    }
+
+    Ok(config)
+}
+
+pub fn kind(id: &str) -> &str {
+    id.split('+').next().unwrap_or(id)
+}
+
 pub trait SourceClone {
     fn clone_box(&self) -> Box<dyn Source>;
 }
 
-pub fn from_dependency_options(options: &DependencyOptions) -> Option<Box<dyn Source>> {
-    match options {
+pub fn from_dependency_options(
+    options: &DependencyOptions,
+    registries: &Registries,
+) -> std::io::Result<Box<dyn Source>> {
+    let source: Box<dyn Source> = match options {
         DependencyOptions::Git {
             repo,
             branch,
             rev,
             tag,
-        } => Some(Box::new(GitSource {
+            path,
+        } => Box::new(GitSource {
             repo: repo.clone(),
             branch: branch.clone(),
             rev: rev.clone(),
             tag: tag.clone(),
-        })),
-        DependencyOptions::Dir { dir: path } => Some(Box::new(DirSource {
+            path: path.clone(),
+        }),
+        DependencyOptions::Dir { dir: path } => Box::new(DirSource {
             path: path.to_path_buf(),
-        })),
-        DependencyOptions::File { file: path } => Some(Box::new(FileSource {
+        }),
+        DependencyOptions::File {
+            file: path,
+            checksum,
+        } => Box::new(FileSource {
             path: path.to_path_buf(),
-        })),
-        DependencyOptions::Url { url } => Some(Box::new(UrlSource {
+            checksum: checksum.clone(),
+        }),
+        DependencyOptions::Url { url, checksum } => Box::new(UrlSource {
             url: url.to_string(),
-        })),
-        DependencyOptions::Registry { version } => Some(Box::new(RegistrySource {
+            checksum: checksum.clone(),
+        }),
+        DependencyOptions::Registry { version, registry } => Box::new(RegistrySource {
+            url: crate::registry::url(registry.as_deref(), registries)?,
+            registry: registry.clone(),
             version: version.to_string(),
-        })),
-    }
+            resolved: None,
+            rev: None,
+        }),
+    };
+
+    Ok(source)
 }
 
 impl<T> SourceClone for T
@@ -1,4 +1,7 @@
+use crate::config::Config;
+use crate::config::DependencyOptions;
 use crate::dependency::Dependency;
+use crate::source::Resolved;
 use crate::source::Source;
 use log::*;
 use std::path::Path;
@@ -10,10 +13,18 @@ pub struct DirSource {
 }
 
 impl Source for DirSource {
-    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<()> {
+    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<Resolved> {
         let project_dir = destination.parent().unwrap();
         let source = project_dir.join(&self.path);
         let destination = destination.join(dependency.clone().name);
+
+        if !source.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Could not find directory {}", source.display()),
+            ));
+        }
+
         trace!(
             "Installing directory from {} to {}",
             source.display(),
@@ -22,6 +33,26 @@ impl Source for DirSource {
 
         crate::util::dir::copy_directory(&source, destination)?;
 
-        Ok(())
+        Ok(Resolved::default())
+    }
+
+    fn id(&self) -> String {
+        format!("dir+{}", self.path.display())
+    }
+
+    fn package_config(
+        &self,
+        _dependency: &Dependency,
+        destination: &Path,
+    ) -> std::io::Result<Config> {
+        let project_dir = destination.parent().unwrap();
+
+        crate::source::package_config(&project_dir.join(&self.path))
+    }
+
+    fn options(&self) -> DependencyOptions {
+        DependencyOptions::Dir {
+            dir: self.path.clone(),
+        }
     }
 }
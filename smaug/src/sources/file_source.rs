@@ -1,26 +1,26 @@
+use crate::config::DependencyOptions;
 use crate::dependency::Dependency;
+use crate::source::Resolved;
 use crate::source::Source;
 use crate::sources::dir_source::DirSource;
+use crate::util::archive;
+use crate::util::digest;
 use log::*;
 use std::path::Path;
 use std::path::PathBuf;
 use walkdir::WalkDir;
-use zip_extensions::zip_extract;
 
 #[derive(Clone, Debug)]
 pub struct FileSource {
     pub path: PathBuf,
+    pub checksum: Option<String>,
 }
 
 impl Source for FileSource {
-    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<()> {
+    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<Resolved> {
         trace!("Installing file at {}", self.path.display());
-        let cached = crate::smaug::cache_dir().join(dependency.clone().name);
 
-        rm_rf::ensure_removed(cached.clone()).expect("Couldn't remove directory");
-
-        trace!("Extracting zip to {}", cached.display());
-        zip_extract(&self.path.to_path_buf(), &cached)?;
+        let cached = self.extract(dependency)?;
 
         trace!(
             "Finding top level package directory in {}",
@@ -35,6 +35,48 @@ impl Source for FileSource {
             Some(dir) => DirSource { path: dir }.install(dependency, destination),
         }
     }
+
+    fn id(&self) -> String {
+        format!("file+{}", self.path.display())
+    }
+
+    fn options(&self) -> DependencyOptions {
+        DependencyOptions::File {
+            file: self.path.clone(),
+            checksum: self.checksum.clone(),
+        }
+    }
+
+    fn fetch(&self, dependency: &Dependency) -> std::io::Result<Box<dyn Source>> {
+        self.extract(dependency)?;
+
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl FileSource {
+    /// Extracts the archive into the cache, unless that was already done, and returns where.
+    fn extract(&self, dependency: &Dependency) -> std::io::Result<PathBuf> {
+        if let Some(checksum) = &self.checksum {
+            digest::verify(&dependency.name, &self.path, checksum)?;
+        }
+
+        let cached = crate::cache::extracted_dir(&self.path)?;
+        let _lock = crate::cache::lock(&cached);
+
+        if cached.is_dir() {
+            trace!("Using extracted archive at {}", cached.display());
+        } else {
+            let partial = cached.with_extension("part");
+            rm_rf::ensure_removed(partial.clone()).expect("Couldn't remove directory");
+
+            trace!("Extracting archive to {}", cached.display());
+            archive::extract(&self.path, &partial)?;
+            std::fs::rename(&partial, &cached)?;
+        }
+
+        Ok(cached)
+    }
 }
 
 fn find_package_dir(path: &Path) -> Option<PathBuf> {
@@ -1,12 +1,28 @@
+use crate::config::Config;
+use crate::config::DependencyOptions;
 use crate::dependency::Dependency;
+use crate::lockfile::LockedPackage;
+use crate::source::Resolved;
 use crate::source::Source;
 use crate::sources::dir_source::DirSource;
+use directories::BaseDirs;
 use git2::build::CheckoutBuilder;
-use git2::build::RepoBuilder;
+use git2::AutotagOption;
+use git2::Cred;
+use git2::CredentialType;
+use git2::Direction;
+use git2::ErrorClass;
+use git2::ErrorCode;
 use git2::FetchOptions;
 use git2::Oid;
+use git2::Remote;
+use git2::RemoteCallbacks;
+use git2::Repository;
 use log::*;
+use relative_path::RelativePathBuf;
+use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 
 #[derive(Clone, Debug)]
 pub struct GitSource {
@@ -14,74 +30,621 @@ pub struct GitSource {
     pub branch: Option<String>,
     pub rev: Option<String>,
     pub tag: Option<String>,
+    pub path: Option<RelativePathBuf>,
 }
 
 impl Source for GitSource {
-    fn install(&self, dependency: &Dependency, path: &Path) -> std::io::Result<()> {
-        let destination = crate::smaug::cache_dir().join(dependency.clone().name);
+    fn install(&self, dependency: &Dependency, path: &Path) -> std::io::Result<Resolved> {
+        let (commit, worktree) = self.prepare()?;
+        let package_dir = self.package_dir(&worktree);
+
+        // The path is checked when Smaug.toml is read, but a symlink in the repository
+        // could still lead out of it.
+        if let (Ok(package_dir), Ok(worktree)) = (
+            dunce::canonicalize(&package_dir),
+            dunce::canonicalize(&worktree),
+        ) {
+            if !package_dir.starts_with(&worktree) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is outside of the repository",
+                        self.path.as_ref().unwrap()
+                    ),
+                ));
+            }
+        }
+
+        if !package_dir.join("Smaug.toml").is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "No Smaug.toml file found in {}",
+                    self.path
+                        .as_ref()
+                        .map_or("the repository root", |path| path.as_str())
+                ),
+            ));
+        }
+
+        DirSource { path: package_dir }.install(dependency, path)?;
+
+        Ok(Resolved {
+            commit: Some(commit.to_string()),
+            version: None,
+        })
+    }
+
+    fn fetch(&self, _dependency: &Dependency) -> std::io::Result<Box<dyn Source>> {
+        Ok(Box::new(self.pin()?))
+    }
+
+    fn id(&self) -> String {
+        let mut query = vec![];
+
+        if let Some(branch) = &self.branch {
+            query.push(format!("branch={}", branch));
+        }
+
+        if let Some(tag) = &self.tag {
+            query.push(format!("tag={}", tag));
+        }
+
+        if let Some(rev) = &self.rev {
+            query.push(format!("rev={}", rev));
+        }
+
+        if let Some(path) = &self.path {
+            query.push(format!("path={}", path));
+        }
+
+        if query.is_empty() {
+            format!("git+{}", self.repo)
+        } else {
+            format!("git+{}?{}", self.repo, query.join("&"))
+        }
+    }
+
+    /// Directories the package points at inside its own repository are installed from
+    /// the same commit of that repository.
+    fn package_config(
+        &self,
+        _dependency: &Dependency,
+        _destination: &Path,
+    ) -> std::io::Result<Config> {
+        let (commit, worktree) = self.prepare()?;
+        let mut config = crate::source::package_config(&self.package_dir(&worktree))?;
+        let worktree = dunce::canonicalize(&worktree)?;
+
+        for (_, options) in config.dependencies.iter_mut() {
+            if let DependencyOptions::Dir { dir } = options {
+                let path = dir.strip_prefix(&worktree).map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("{} is outside of the repository", dir.display()),
+                    )
+                })?;
+                let path = RelativePathBuf::from_path(path).map_err(|err| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+                })?;
+
+                *options = DependencyOptions::Git {
+                    repo: self.repo.clone(),
+                    branch: None,
+                    rev: Some(commit.to_string()),
+                    tag: None,
+                    path: if path.as_str().is_empty() {
+                        None
+                    } else {
+                        Some(path)
+                    },
+                };
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn options(&self) -> DependencyOptions {
+        DependencyOptions::Git {
+            repo: self.repo.clone(),
+            branch: self.branch.clone(),
+            rev: self.rev.clone(),
+            tag: self.tag.clone(),
+            path: self.path.clone(),
+        }
+    }
+
+    fn locked(&self, locked: &LockedPackage) -> Box<dyn Source> {
+        match &locked.commit {
+            None => Box::new(self.clone()),
+            Some(commit) => Box::new(GitSource {
+                repo: self.repo.clone(),
+                branch: self.branch.clone(),
+                rev: Some(commit.clone()),
+                tag: None,
+                path: self.path.clone(),
+            }),
+        }
+    }
+}
+
+impl GitSource {
+    /// A copy of this source pinned to the commit its branch or tag points to right now.
+    /// It installs from the cache without going back to the remote.
+    pub fn pin(&self) -> std::io::Result<GitSource> {
+        let (commit, _) = self.prepare()?;
+
+        Ok(GitSource {
+            rev: Some(commit.to_string()),
+            tag: None,
+            ..self.clone()
+        })
+    }
+
+    /// Where the package lives inside a checkout of the repository.
+    fn package_dir(&self, worktree: &Path) -> PathBuf {
+        match &self.path {
+            Some(subdirectory) => subdirectory.to_path(worktree),
+            None => worktree.to_path_buf(),
+        }
+    }
+
+    /// Makes sure the requested commit is checked out in the cache, and returns it along
+    /// with where it was checked out.
+    fn prepare(&self) -> std::io::Result<(Oid, PathBuf)> {
+        let database = crate::cache::git_dir(self.repo.as_str());
         trace!(
-            "Installing git repository {} to {}",
+            "Preparing git repository {} in {}",
             self.repo,
-            destination.display()
+            database.display()
         );
 
-        if destination.exists() {
-            trace!("Removing directory {}", destination.to_str().unwrap());
-            rm_rf::ensure_removed(destination.clone()).unwrap();
+        let _lock = crate::cache::lock(&database);
+        let repository = open_database(&database)?;
+
+        if !crate::smaug::offline() && !self.is_cached(&repository) {
+            self.fetch_refs(&repository)?;
         }
 
-        let fetch = FetchOptions::new();
-        let checkout = CheckoutBuilder::new();
+        let commit = self.resolve(&repository)?;
+        debug!("Commit: {}", commit);
 
-        let mut builder = RepoBuilder::new();
-        builder.fetch_options(fetch);
-        builder.with_checkout(checkout);
+        let worktree = crate::cache::git_checkout_dir(self.repo.as_str(), &commit.to_string());
 
-        debug!("Repository: {}", self.repo);
-        if self.branch.is_some() {
-            debug!("Branch: {}", self.branch.as_ref().unwrap());
-            builder.branch(self.branch.as_ref().unwrap().as_str());
+        if worktree.is_dir() {
+            trace!("Using checkout at {}", worktree.display());
+        } else {
+            checkout(&repository, commit, &worktree)?;
         }
 
-        trace!(
-            "Cloning git repository to {}",
-            destination.to_str().unwrap()
-        );
-        let repository = builder.clone(&self.repo, destination.as_path()).unwrap();
+        Ok((commit, worktree))
+    }
 
-        if self.rev.is_some() {
-            let revision = self.rev.clone().unwrap();
-            debug!("Revision: {}", revision);
-            let mut checkout = CheckoutBuilder::new();
-            let object = repository
-                .find_object(
-                    Oid::from_str(revision.as_str()).expect("Could not find the revision"),
-                    None,
-                )
-                .unwrap();
-            repository
-                .reset(&object, git2::ResetType::Hard, Some(&mut checkout))
-                .unwrap();
+    /// Fetches only the refs this source needs into the shared repository.
+    fn fetch_refs(&self, repository: &Repository) -> std::io::Result<()> {
+        let refspecs: Vec<String> = if self.rev.is_some() {
+            // Servers don't generally let us ask for a single commit, so look everywhere for it.
+            vec![
+                "+refs/heads/*:refs/remotes/origin/*".to_string(),
+                "+refs/tags/*:refs/tags/*".to_string(),
+            ]
+        } else if let Some(tag) = &self.tag {
+            vec![format!("+refs/tags/{0}:refs/tags/{0}", tag)]
+        } else if let Some(branch) = &self.branch {
+            vec![format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch)]
+        } else {
+            vec!["+HEAD:refs/remotes/origin/HEAD".to_string()]
+        };
+
+        trace!("Fetching {} from {}", refspecs.join(", "), self.repo);
+
+        // libgit2 can't make shallow fetches yet. Narrow refspecs keep the download
+        // down to the history we actually ask for.
+        let mut options = FetchOptions::new();
+        options.download_tags(AutotagOption::None);
+        options.remote_callbacks(remote_callbacks());
+
+        let mut remote = repository
+            .remote_anonymous(self.repo.as_str())
+            .map_err(git_error)?;
+
+        remote
+            .fetch(&refspecs, Some(&mut options), None)
+            .map_err(git_error)
+    }
+
+    /// Revisions and tags don't move, so a repository that has them doesn't need a fetch.
+    /// Branches always need a fresh look at the remote.
+    fn is_cached(&self, repository: &Repository) -> bool {
+        if let Some(rev) = &self.rev {
+            return Oid::from_str(rev.as_str())
+                .and_then(|oid| repository.find_commit(oid))
+                .is_ok();
+        }
+
+        if let Some(tag) = &self.tag {
+            return repository
+                .revparse_single(format!("refs/tags/{}", tag).as_str())
+                .is_ok();
         }
 
-        if self.tag.is_some() {
-            let tag = self.tag.clone().unwrap();
-            let mut checkout = CheckoutBuilder::new();
+        false
+    }
 
-            let rev = repository
-                .revparse_single(&tag)
-                .expect("Couldn't parse tag");
+    /// Finds the commit the requested revision, tag or branch points to.
+    fn resolve(&self, repository: &Repository) -> std::io::Result<Oid> {
+        let (spec, name) = if let Some(rev) = &self.rev {
+            (rev.clone(), rev.clone())
+        } else if let Some(tag) = &self.tag {
+            (format!("refs/tags/{}", tag), tag.clone())
+        } else if let Some(branch) = &self.branch {
+            (format!("refs/remotes/origin/{}", branch), branch.clone())
+        } else {
+            (
+                "refs/remotes/origin/HEAD".to_string(),
+                "the default branch".to_string(),
+            )
+        };
 
-            repository
-                .reset(&rev, git2::ResetType::Hard, Some(&mut checkout))
-                .unwrap();
+        let commit = repository
+            .revparse_single(spec.as_str())
+            .and_then(|object| object.peel_to_commit());
+
+        match commit {
+            Ok(commit) => Ok(commit.id()),
+            Err(..) if crate::smaug::offline() => Err(crate::cache::not_cached(
+                format!("{} of {}", name, self.repo).as_str(),
+            )),
+            Err(..) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Couldn't find {} in {}", name, self.repo),
+            )),
         }
+    }
+}
+
+/// Opens the bare repository every package from the same URL shares, creating it if needed.
+fn open_database(database: &Path) -> std::io::Result<Repository> {
+    if let Ok(repository) = Repository::open_bare(database) {
+        return Ok(repository);
+    }
+
+    trace!("Creating git repository at {}", database.display());
+    std::fs::create_dir_all(database)?;
+    Repository::init_bare(database).map_err(git_error)
+}
+
+/// Writes the files of a commit to their own directory, leaving the repository untouched.
+fn checkout(repository: &Repository, commit: Oid, worktree: &Path) -> std::io::Result<()> {
+    trace!("Checking out {} to {}", commit, worktree.display());
+
+    let partial = worktree.with_extension("part");
+    rm_rf::ensure_removed(&partial).expect("Couldn't remove directory");
+    std::fs::create_dir_all(&partial)?;
 
-        let cached = repository.path().parent().expect("No parent dir");
+    let commit = repository.find_commit(commit).map_err(git_error)?;
+    let mut builder = CheckoutBuilder::new();
+    builder.target_dir(&partial).force();
 
-        DirSource {
-            path: cached.to_path_buf(),
+    repository
+        .checkout_tree(commit.as_object(), Some(&mut builder))
+        .map_err(git_error)?;
+
+    std::fs::rename(&partial, worktree)
+}
+
+/// Hands libgit2 one set of credentials at a time, in order: ssh-agent, the default
+/// SSH keys, a token for the host from SMAUG_GIT_TOKEN_<HOST> or the user config, git
+/// credential helpers and finally the system defaults. Each is only tried once so a
+/// rejected login ends with an error instead of an endless retry.
+#[derive(Default)]
+struct Credentials {
+    tried_username: bool,
+    tried_agent: bool,
+    keys: Option<Vec<PathBuf>>,
+    tried_token: bool,
+    tried_helper: bool,
+    tried_default: bool,
+}
+
+impl Credentials {
+    fn next(
+        &mut self,
+        url: &str,
+        username: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let ssh_username = username.unwrap_or("git");
+
+        if allowed.contains(CredentialType::USERNAME) && !self.tried_username {
+            self.tried_username = true;
+            return Cred::username(ssh_username);
         }
-        .install(dependency, path)
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !self.tried_agent {
+                self.tried_agent = true;
+                trace!("Trying ssh-agent for {}", url);
+
+                if let Ok(cred) = Cred::ssh_key_from_agent(ssh_username) {
+                    return Ok(cred);
+                }
+            }
+
+            let keys = self.keys.get_or_insert_with(default_ssh_keys);
+
+            while let Some(key) = keys.pop() {
+                if key.is_file() {
+                    trace!("Trying SSH key {} for {}", key.display(), url);
+                    return Cred::ssh_key(ssh_username, None, &key, None);
+                }
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if !self.tried_token {
+                self.tried_token = true;
+
+                if let Some(token) = git_token(url) {
+                    trace!("Trying token for {}", url);
+                    return Cred::userpass_plaintext(
+                        username.unwrap_or("x-access-token"),
+                        token.as_str(),
+                    );
+                }
+            }
+
+            if !self.tried_helper {
+                self.tried_helper = true;
+                trace!("Trying git credential helpers for {}", url);
+
+                let helper = git2::Config::open_default()
+                    .and_then(|config| Cred::credential_helper(&config, url, username));
+
+                if let Ok(cred) = helper {
+                    return Ok(cred);
+                }
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && !self.tried_default {
+            self.tried_default = true;
+            return Cred::default();
+        }
+
+        Err(git2::Error::new(
+            ErrorCode::Auth,
+            ErrorClass::Net,
+            format!(
+                "Couldn't authenticate with {}. Smaug tried ssh-agent, your SSH keys, \
+                 a token for its host and your git credential helpers.",
+                url
+            ),
+        ))
+    }
+}
+
+fn remote_callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut credentials = Credentials::default();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| credentials.next(url, username, allowed));
+
+    callbacks
+}
+
+/// The keys ssh looks for by default, in reverse so the preferred key is popped first.
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let ssh_dir = match BaseDirs::new() {
+        Some(dirs) => dirs.home_dir().join(".ssh"),
+        None => return vec![],
+    };
+
+    ["id_rsa", "id_ecdsa", "id_ed25519"]
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .collect()
+}
+
+/// Finds the token for the host of `url`. Tokens are only sent to the host they were
+/// set for, and only over https, so a package can't collect them by pointing a
+/// dependency at a server it controls.
+fn git_token(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?.to_lowercase();
+    let variable = format!(
+        "SMAUG_GIT_TOKEN_{}",
+        host.to_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    );
+
+    let token = match std::env::var(variable) {
+        Ok(token) => Some(token),
+        Err(..) => match crate::user_config::load() {
+            Ok(config) => configured_token(config.git.tokens, host.as_str()),
+            Err(err) => {
+                warn!("{}", err);
+                None
+            }
+        },
+    };
+
+    if token.is_some() && url.scheme() != "https" {
+        warn!("Not sending your token for {} over {}", host, url.scheme());
+        return None;
+    }
+
+    token
+}
+
+/// The token set for `host` in `git.tokens`, whichever way the host was capitalized.
+fn configured_token(tokens: HashMap<String, String>, host: &str) -> Option<String> {
+    tokens
+        .into_iter()
+        .find(|(name, _)| name.to_lowercase() == host)
+        .map(|(_, token)| token)
+}
+
+fn git_error(err: git2::Error) -> std::io::Error {
+    if err.code() == ErrorCode::Auth {
+        // Errors raised from our own callbacks come back with their class and code appended.
+        let message = err.message().split("; class=").next().unwrap_or_default();
+
+        return std::io::Error::new(std::io::ErrorKind::PermissionDenied, message);
+    }
+
+    std::io::Error::other(err.message())
+}
+
+/// Looks up the commit a remote branch currently points to, or the remote's HEAD
+/// when no branch is given.
+pub fn remote_head(repo: &str, branch: Option<&str>) -> std::io::Result<String> {
+    let reference = match branch {
+        Some(branch) => format!("refs/heads/{}", branch),
+        None => "HEAD".to_string(),
+    };
+    trace!("Looking up {} in {}", reference, repo);
+
+    let mut remote = Remote::create_detached(repo).map_err(git_error)?;
+    let connection = remote
+        .connect_auth(Direction::Fetch, Some(remote_callbacks()), None)
+        .map_err(git_error)?;
+
+    let head = connection
+        .list()
+        .map_err(git_error)?
+        .iter()
+        .find(|head| head.name() == reference)
+        .map(|head| head.oid().to_string());
+
+    match head {
+        Some(head) => Ok(head),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Could not find {} in {}", reference, repo),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A host only these tests use, so the tokens they set don't leak into other tests.
+    fn host(name: &str) -> String {
+        format!("{}.smaug-{}.example", name, std::process::id())
+    }
+
+    fn set_token(host: &str, token: &str) {
+        let variable = format!(
+            "SMAUG_GIT_TOKEN_{}",
+            host.to_uppercase()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+        std::env::set_var(variable, token);
+    }
+
+    #[test]
+    fn tokens_are_only_sent_over_https() {
+        let host = host("plain");
+        set_token(&host, "secret");
+
+        assert_eq!(git_token(&format!("http://{}/game.git", host)), None);
+        assert_eq!(
+            git_token(&format!("https://{}/game.git", host)),
+            Some("secret".to_string())
+        );
+    }
+
+    #[test]
+    fn tokens_are_only_sent_to_their_own_host() {
+        set_token(&host("mine"), "secret");
+
+        assert_eq!(
+            git_token(&format!("https://{}/game.git", host("theirs"))),
+            None
+        );
+    }
+
+    #[test]
+    fn hosts_are_matched_ignoring_case() {
+        let host = host("mixed");
+        set_token(&host, "secret");
+
+        assert_eq!(
+            git_token(&format!("https://{}/game.git", host.to_uppercase())),
+            Some("secret".to_string())
+        );
+
+        let mut tokens = HashMap::new();
+        tokens.insert("GitHub.com".to_string(), "secret".to_string());
+
+        assert_eq!(
+            configured_token(tokens.clone(), "github.com"),
+            Some("secret".to_string())
+        );
+        assert_eq!(configured_token(tokens, "gitlab.com"), None);
+    }
+
+    #[test]
+    fn credentials_are_tried_in_order_then_given_up() {
+        let ssh_dir = std::env::temp_dir().join(format!("smaug-ssh-{}", std::process::id()));
+        std::fs::create_dir_all(&ssh_dir).unwrap();
+        let key = ssh_dir.join("id_ed25519");
+        std::fs::write(&key, "").unwrap();
+
+        let host = host("credentials");
+        set_token(&host, "secret");
+        let url = format!("https://{}/game.git", host);
+
+        // Keys are popped from the end, and the missing one is skipped.
+        let mut credentials = Credentials {
+            keys: Some(vec![key, ssh_dir.join("id_rsa")]),
+            ..Credentials::default()
+        };
+        let allowed = CredentialType::USERNAME
+            | CredentialType::SSH_KEY
+            | CredentialType::USER_PASS_PLAINTEXT
+            | CredentialType::DEFAULT;
+
+        let mut tried = vec![];
+        let error = loop {
+            match credentials.next(&url, None, allowed) {
+                Ok(cred) => tried.push(CredentialType::from_bits_truncate(cred.credtype())),
+                Err(err) => break err,
+            }
+        };
+        std::fs::remove_dir_all(&ssh_dir).unwrap();
+
+        // A git credential helper may answer between the token and the default.
+        assert_eq!(
+            tried[..4].to_vec(),
+            vec![
+                CredentialType::USERNAME,
+                CredentialType::SSH_KEY,
+                CredentialType::SSH_KEY,
+                CredentialType::USER_PASS_PLAINTEXT,
+            ]
+        );
+        assert_eq!(tried.last(), Some(&CredentialType::DEFAULT));
+        assert_eq!(error.code(), ErrorCode::Auth);
+        assert!(credentials.next(&url, None, allowed).is_err());
+    }
+
+    #[test]
+    fn credentials_only_offer_what_is_allowed() {
+        let mut credentials = Credentials::default();
+        let url = format!("https://{}/game.git", host("allowed"));
+
+        let cred = credentials
+            .next(&url, Some("git"), CredentialType::USERNAME)
+            .unwrap();
+        assert_eq!(
+            CredentialType::from_bits_truncate(cred.credtype()),
+            CredentialType::USERNAME
+        );
+        assert!(credentials
+            .next(&url, Some("git"), CredentialType::USERNAME)
+            .is_err());
     }
 }
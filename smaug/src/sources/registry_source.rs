@@ -1,13 +1,28 @@
+use crate::config::DependencyOptions;
 use crate::dependency::Dependency;
+use crate::lockfile::LockedPackage;
+use crate::source::Resolved;
 use crate::source::Source;
 use crate::sources::git_source::GitSource;
 use log::*;
+use semver::{Version, VersionReq};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::path::Path;
 
 #[derive(Clone, Debug)]
 pub struct RegistrySource {
+    pub url: String,
+    pub registry: Option<String>,
     pub version: String,
+    pub resolved: Option<Version>,
+    pub rev: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Published {
+    pub version: Version,
+    pub yanked: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,58 +41,201 @@ struct PackageResponse {
     version: VersionResponse,
 }
 
+#[derive(Debug, Deserialize)]
+struct PublishedVersionResponse {
+    version: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionsResponse {
+    versions: Vec<PublishedVersionResponse>,
+}
+
 impl Source for RegistrySource {
-    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<()> {
-        trace!(
-            "Fetching {} version {} from registry",
-            dependency.clone().name,
-            self.version
-        );
+    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<Resolved> {
+        let (version, source) = self.git_source(&dependency.name)?;
+        let resolved = source.install(dependency, destination)?;
+
+        Ok(Resolved {
+            version: Some(version.to_string()),
+            ..resolved
+        })
+    }
+
+    fn id(&self) -> String {
+        match &self.registry {
+            Some(registry) => format!("registry+{}?registry={}", self.version, registry),
+            None => format!("registry+{}", self.version),
+        }
+    }
+
+    fn options(&self) -> DependencyOptions {
+        DependencyOptions::Registry {
+            version: self.version.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+
+    fn locked(&self, locked: &LockedPackage) -> Box<dyn Source> {
+        Box::new(RegistrySource {
+            url: self.url.clone(),
+            registry: self.registry.clone(),
+            version: self.version.clone(),
+            resolved: Version::parse(locked.version.as_str()).ok(),
+            rev: locked.commit.clone(),
+        })
+    }
+
+    fn fetch(&self, dependency: &Dependency) -> std::io::Result<Box<dyn Source>> {
+        let (version, source) = self.git_source(&dependency.name)?;
+        let pinned = source.pin()?;
 
-        let source = fetch_from_registry(dependency.name.clone(), self.version.clone())?;
+        Ok(Box::new(RegistrySource {
+            resolved: Some(version),
+            rev: pinned.rev,
+            ..self.clone()
+        }))
+    }
 
-        source.install(dependency, destination)
+    fn with_version(&self, version: &Version) -> Box<dyn Source> {
+        Box::new(RegistrySource {
+            url: self.url.clone(),
+            registry: self.registry.clone(),
+            version: self.version.clone(),
+            resolved: Some(version.clone()),
+            rev: None,
+        })
     }
 }
 
-fn fetch_from_registry(name: String, version: String) -> std::io::Result<GitSource> {
-    let url = format!(
-        "https://api.smaug.dev/packages/{}/versions/{}",
-        name, version
-    );
-    trace!("Fetching from {}", url);
-    let response = reqwest::blocking::get(url.as_str());
+impl RegistrySource {
+    /// The version to install, along with the git repository it was published from.
+    fn git_source(&self, name: &str) -> std::io::Result<(Version, GitSource)> {
+        let version = match &self.resolved {
+            Some(version) => version.clone(),
+            None => self.select(name)?,
+        };
 
-    match response {
-        Err(..) => Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "couldn't find package",
-        )),
-        Ok(response) => parse_response(response, name, version),
+        trace!("Fetching {} version {} from registry", name, version);
+
+        let mut source =
+            fetch_from_registry(self.url.as_str(), name.to_string(), version.to_string())?;
+
+        if self.rev.is_some() {
+            source.tag = None;
+            source.rev = self.rev.clone();
+        }
+
+        Ok((version, source))
     }
+
+    /// Picks the highest published version that satisfies the requirement.
+    fn select(&self, name: &str) -> std::io::Result<Version> {
+        let requirement = VersionReq::parse(self.version.as_str()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a valid version requirement", self.version),
+            )
+        })?;
+        let published = fetch_versions(self.url.as_str(), name)?;
+
+        highest(&published, |version| requirement.matches(version)).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No published version of {} matches {}", name, requirement),
+            )
+        })
+    }
+}
+
+/// The highest version that hasn't been yanked and passes the check.
+pub fn highest<F>(published: &[Published], matches: F) -> Option<Version>
+where
+    F: Fn(&Version) -> bool,
+{
+    published
+        .iter()
+        .filter(|published| !published.yanked && matches(&published.version))
+        .map(|published| published.version.clone())
+        .max()
 }
 
-fn parse_response(
-    response: reqwest::blocking::Response,
+fn fetch_from_registry(
+    registry: &str,
     name: String,
     version: String,
 ) -> std::io::Result<GitSource> {
-    if response.status().is_success() {
-        let package_response: PackageResponse =
-            response.json().expect("Couldn't parse registry response");
-        Ok(GitSource {
-            repo: package_response.version.repository.url,
-            tag: Some(package_response.version.repository.tag),
-            rev: None,
-            branch: None,
-        })
-    } else {
-        Err(std::io::Error::new(
+    let url = format!("{}/packages/{}/versions/{}", registry, name, version);
+    trace!("Fetching from {}", url);
+
+    // Published versions never change, so a cached response is as good as a fresh one.
+    let response = crate::cache::download(url.as_str(), false).map_err(|err| {
+        if crate::smaug::offline() {
+            return err;
+        }
+
+        std::io::Error::new(
             std::io::ErrorKind::NotFound,
             format!(
                 "Couldn't fetch {} version {} from repository",
                 name, version
             ),
-        ))
-    }
+        )
+    })?;
+
+    let package_response: PackageResponse = parse_response(&response)?;
+
+    Ok(GitSource {
+        repo: package_response.version.repository.url,
+        tag: Some(package_response.version.repository.tag),
+        rev: None,
+        branch: None,
+        path: None,
+    })
+}
+
+fn parse_response<T: DeserializeOwned>(path: &Path) -> std::io::Result<T> {
+    let contents = std::fs::read(path)?;
+
+    serde_json::from_slice(&contents).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Couldn't parse registry response",
+        )
+    })
+}
+
+pub fn fetch_versions(registry: &str, name: &str) -> std::io::Result<Vec<Published>> {
+    let url = format!("{}/packages/{}/versions", registry, name);
+    trace!("Fetching versions from {}", url);
+
+    let response = crate::cache::download(url.as_str(), true).map_err(|err| {
+        if crate::smaug::offline() {
+            return err;
+        }
+
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Couldn't fetch the versions of {} from repository", name),
+        )
+    })?;
+
+    let versions_response: VersionsResponse = parse_response(&response)?;
+
+    let versions = versions_response
+        .versions
+        .iter()
+        .filter_map(|published| {
+            Version::parse(published.version.as_str())
+                .ok()
+                .map(|version| Published {
+                    version,
+                    yanked: published.yanked,
+                })
+        })
+        .collect();
+
+    Ok(versions)
 }
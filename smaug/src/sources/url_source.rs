@@ -1,39 +1,58 @@
+use crate::config::DependencyOptions;
 use crate::dependency::Dependency;
+use crate::source::Resolved;
 use crate::source::Source;
 use crate::sources::file_source::FileSource;
+use crate::util::digest;
 use log::*;
-use std::fs::File;
 use std::path::Path;
 
 #[derive(Clone, Debug)]
 pub struct UrlSource {
     pub url: String,
+    pub checksum: Option<String>,
 }
 
 impl Source for UrlSource {
-    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<()> {
-        trace!("Downloading Url from {}", self.url);
-        let file_name = format!("{}.zip", dependency.clone().name);
-        let cached = crate::smaug::cache_dir().join(file_name);
+    fn install(&self, dependency: &Dependency, destination: &Path) -> std::io::Result<Resolved> {
+        self.download(dependency)?.install(dependency, destination)
+    }
+
+    fn id(&self) -> String {
+        format!("url+{}", self.url)
+    }
 
-        if cached.exists() {
-            std::fs::remove_file(cached.clone())?;
+    fn options(&self) -> DependencyOptions {
+        DependencyOptions::Url {
+            url: self.url.clone(),
+            checksum: self.checksum.clone(),
         }
+    }
+
+    fn fetch(&self, dependency: &Dependency) -> std::io::Result<Box<dyn Source>> {
+        self.download(dependency)?.fetch(dependency)?;
 
-        trace!("Downloading package to {}", cached.display());
-        std::fs::create_dir_all(cached.parent().unwrap())?;
-        let mut file = File::create(cached.clone())?;
-        let response = reqwest::blocking::get(self.url.as_str());
-
-        match response {
-            Err(..) => Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Couldn't download file.",
-            )),
-            Ok(mut response) => {
-                std::io::copy(&mut response, &mut file)?;
-                FileSource { path: cached }.install(dependency, destination)
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl UrlSource {
+    fn download(&self, dependency: &Dependency) -> std::io::Result<FileSource> {
+        trace!("Downloading Url from {}", self.url);
+        let mut cached = crate::cache::download(self.url.as_str(), false)?;
+
+        // The file behind the URL may have changed since it was cached, so look again before giving up.
+        if let Some(checksum) = &self.checksum {
+            if digest::verify(&dependency.name, &cached, checksum).is_err()
+                && !crate::smaug::offline()
+            {
+                cached = crate::cache::download(self.url.as_str(), true)?;
             }
         }
+
+        Ok(FileSource {
+            path: cached,
+            checksum: self.checksum.clone(),
+        })
     }
 }
@@ -0,0 +1,66 @@
+use crate::config::Registries;
+use derive_more::Display;
+use derive_more::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Settings that belong to the person running Smaug rather than to a project.
+#[derive(Debug, Default, Deserialize)]
+pub struct UserConfig {
+    #[serde(default)]
+    pub git: Git,
+    #[serde(default)]
+    pub registries: Registries,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Git {
+    /// Tokens by the host they are sent to, like `github.com`.
+    #[serde(default)]
+    pub tokens: HashMap<String, String>,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum Error {
+    #[display(
+        fmt = "Could not read your Smaug configuration at {}: {}",
+        "path.display()",
+        "parent"
+    )]
+    ReadError {
+        path: PathBuf,
+        parent: std::io::Error,
+    },
+    #[display(
+        fmt = "Could not parse your Smaug configuration at {}: {}",
+        "path.display()",
+        "parent"
+    )]
+    ParseError {
+        path: PathBuf,
+        parent: toml::de::Error,
+    },
+}
+
+pub fn path() -> PathBuf {
+    crate::smaug::config_dir().join("config.toml")
+}
+
+pub fn load() -> Result<UserConfig, Error> {
+    let path = path();
+
+    if !path.is_file() {
+        return Ok(UserConfig::default());
+    }
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => return Err(Error::ReadError { path, parent: err }),
+    };
+
+    match toml::from_str(contents.as_str()) {
+        Ok(config) => Ok(config),
+        Err(err) => Err(Error::ParseError { path, parent: err }),
+    }
+}
@@ -0,0 +1,115 @@
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+}
+
+/// Works out the archive format from the first bytes of the file, so downloads
+/// don't need a meaningful file name.
+pub fn detect(path: &Path) -> io::Result<Option<Format>> {
+    let mut header = Vec::with_capacity(262);
+    File::open(path)?.take(262).read_to_end(&mut header)?;
+
+    let format = if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Some(Format::Zip)
+    } else if header.starts_with(&[0x1f, 0x8b]) {
+        Some(Format::TarGz)
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Format::TarXz)
+    } else if header.len() >= 262 && &header[257..262] == b"ustar" {
+        Some(Format::Tar)
+    } else {
+        None
+    };
+
+    Ok(format)
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && matches!(detect(path), Ok(Some(..)))
+}
+
+pub fn extract(path: &Path, destination: &Path) -> io::Result<()> {
+    let format = match detect(path)? {
+        Some(format) => format,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not a zip, tar, tar.gz or tar.xz archive",
+                    path.display()
+                ),
+            ))
+        }
+    };
+
+    std::fs::create_dir_all(destination)?;
+
+    match format {
+        Format::Zip => zip_extensions::zip_extract(&path.to_path_buf(), &destination.to_path_buf())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        Format::Tar => tar::Archive::new(File::open(path)?).unpack(destination),
+        Format::TarGz => tar::Archive::new(GzDecoder::new(File::open(path)?)).unpack(destination),
+        Format::TarXz => tar::Archive::new(XzDecoder::new(File::open(path)?)).unpack(destination),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn write(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("smaug-archive-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    fn detected(name: &str, contents: &[u8]) -> Option<Format> {
+        let path = write(name, contents);
+        let format = detect(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        format
+    }
+
+    #[test]
+    fn detects_formats_from_their_magic_bytes() {
+        assert_eq!(detected("zip", b"PK\x03\x04rest"), Some(Format::Zip));
+        assert_eq!(detected("empty-zip", b"PK\x05\x06rest"), Some(Format::Zip));
+        assert_eq!(
+            detected("gz", &[0x1f, 0x8b, 0x08, 0x00]),
+            Some(Format::TarGz)
+        );
+        assert_eq!(
+            detected("xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00]),
+            Some(Format::TarXz)
+        );
+    }
+
+    #[test]
+    fn detects_tar_from_the_ustar_header() {
+        let mut header = vec![0; 512];
+        header[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(detected("tar", &header), Some(Format::Tar));
+    }
+
+    #[test]
+    fn ignores_files_that_are_not_archives() {
+        assert_eq!(detected("text", b"# Smaug.toml"), None);
+        assert_eq!(detected("short", b"PK"), None);
+        assert_eq!(detected("nothing", b""), None);
+    }
+}
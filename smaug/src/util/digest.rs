@@ -1,6 +1,8 @@
 use blake2::{Blake2b, Digest};
+use sha2::Sha256;
 use std::path::Path;
 use std::{fs, io};
+use walkdir::WalkDir;
 
 pub fn file(path: &Path) -> io::Result<String> {
     let mut file = fs::File::open(path)?;
@@ -10,3 +12,68 @@ pub fn file(path: &Path) -> io::Result<String> {
 
     Ok(format!("{:x}", hash))
 }
+
+pub fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    let hash = hasher.finalize();
+
+    Ok(format!("{:x}", hash))
+}
+
+/// Checksums are written as `algorithm:hex`, with either blake2b or sha256 as the algorithm.
+pub fn is_checksum(checksum: &str) -> bool {
+    checksum.starts_with("blake2b:") || checksum.starts_with("sha256:")
+}
+
+/// Makes sure a package's file matches a checksum, explaining the difference when it doesn't.
+pub fn verify(name: &str, path: &Path, checksum: &str) -> io::Result<()> {
+    let actual = if checksum.starts_with("sha256:") {
+        format!("sha256:{}", sha256(path)?)
+    } else if checksum.starts_with("blake2b:") {
+        format!("blake2b:{}", file(path)?)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported checksum {}", checksum),
+        ));
+    };
+
+    if actual.eq_ignore_ascii_case(checksum) {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} does not match its checksum.\nExpected: {}\nActual: {}",
+            name, checksum, actual
+        ),
+    ))
+}
+
+pub fn string(contents: &str) -> String {
+    let hash = Blake2b::digest(contents.as_bytes());
+
+    format!("{:x}", hash)
+}
+
+pub fn directory(path: &Path) -> io::Result<String> {
+    let mut hasher = Blake2b::new();
+
+    for entry in WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry?;
+
+        if entry.file_type().is_file() {
+            let relative = entry.path().strip_prefix(path).unwrap();
+            hasher.update(relative.to_string_lossy().replace('\\', "/").as_bytes());
+            hasher.update([0]);
+            hasher.update(file(entry.path())?.as_bytes());
+        }
+    }
+
+    let hash = hasher.finalize();
+
+    Ok(format!("{:x}", hash))
+}
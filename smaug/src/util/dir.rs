@@ -1,7 +1,9 @@
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use log::*;
+use relative_path::RelativePathBuf;
 use std::fs;
 use std::io;
+use std::path::Component;
 use std::path::Path;
 use walkdir::WalkDir;
 
@@ -18,7 +20,7 @@ pub fn copy_directory<P: AsRef<Path>>(source: &P, destination: P) -> io::Result<
         .expect("Could not parse smaugignore file");
 
     for entry in WalkDir::new(source) {
-        let entry = entry.expect("Could not find directory");
+        let entry = entry?;
         let entry = entry.path();
         let relative = entry.strip_prefix(source.as_ref()).unwrap();
         let new_path = destination.as_ref().join(relative);
@@ -41,6 +43,51 @@ pub fn copy_directory<P: AsRef<Path>>(source: &P, destination: P) -> io::Result<
     Ok(())
 }
 
+/// The path of a file inside the project, as written in Smaug.toml.
+pub fn relative(project_dir: &Path, file: &Path) -> io::Result<RelativePathBuf> {
+    let relative = file.strip_prefix(project_dir).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is outside of the project", file.display()),
+        )
+    })?;
+
+    RelativePathBuf::from_path(relative)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+}
+
+/// Spells `path` from `base`, going up with `..` where needed. Paths on different
+/// drives can't be spelled from each other.
+pub fn path_from(base: &Path, path: &Path) -> Option<RelativePathBuf> {
+    if !base.is_absolute() || !path.is_absolute() {
+        return None;
+    }
+
+    let base: Vec<Component> = base.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = base
+        .iter()
+        .zip(path.iter())
+        .take_while(|(base, path)| base == path)
+        .count();
+
+    if common == 0 {
+        return None;
+    }
+
+    let mut relative = RelativePathBuf::new();
+
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+
+    for component in path[common..].iter() {
+        relative.push(component.as_os_str().to_str()?);
+    }
+
+    Some(relative)
+}
+
 fn is_git_dir(path: &str) -> bool {
     path.contains("/.git/") || path.contains("\\.git\\")
 }
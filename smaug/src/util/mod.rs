@@ -1,2 +1,4 @@
+pub mod archive;
 pub mod digest;
 pub mod dir;
+pub mod parallel;
@@ -0,0 +1,45 @@
+use std::sync::Mutex;
+use std::thread;
+
+/// Runs `f` over every item on up to `jobs` threads, returning the results in the
+/// same order as the items.
+pub fn map<T, R, F>(jobs: usize, items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let count = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<Option<R>>>());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, count.max(1)) {
+            scope.spawn(|| loop {
+                let next = queue.lock().expect("Job queue poisoned").next();
+
+                match next {
+                    Some((index, item)) => {
+                        let result = f(item);
+                        results.lock().expect("Job results poisoned")[index] = Some(result);
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .expect("Job results poisoned")
+        .into_iter()
+        .map(|result| result.expect("Job did not finish"))
+        .collect()
+}
+
+/// The number of jobs to run when none is given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|jobs| jobs.get())
+        .unwrap_or(1)
+}